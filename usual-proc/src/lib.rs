@@ -4,108 +4,218 @@ use proc_macro::TokenStream;
 use proc_macro2::{
     Ident, Literal, Span as Span2, TokenStream as TokenStream2, TokenTree as TokenTree2,
 };
-use quote::quote;
-use regex::{Match, Regex};
-use syn::{parse_macro_input, LitStr};
+use quote::{quote, quote_spanned, ToTokens};
+use regex::{Captures, Match, Regex};
+use std::collections::HashMap;
+use syn::{
+    parenthesized,
+    parse::{Parse, ParseStream},
+    parse_macro_input,
+    punctuated::Punctuated,
+    spanned::Spanned,
+    token, LitStr, Token, Type,
+};
 
 #[proc_macro]
 pub fn query(items: TokenStream) -> TokenStream {
-    let items = proc_macro2::TokenStream::from(items);
-    let mut item_iter = items.clone().into_iter();
+    let QueryInput {
+        text: text_literal,
+        params,
+    } = parse_macro_input!(items as QueryInput);
+    let text = text_literal.value();
 
-    let text = match item_iter.next() {
-        Some(TokenTree2::Literal(literal)) => {
-            let stream = TokenStream2::from(TokenTree2::Literal(literal)).into();
-            parse_macro_input!(stream as LitStr).value()
-        }
-        _ => panic!("The first argument of `query!` must be a string literal."),
+    // The model's name can have generic arguments, which can contain spaces, like
+    // `{Record<i64, String>}`.
+    let re = Regex::new(
+        r"\{([^\{\}:\s<]+(?:<[^\}]*>)?)(?:::(!?[\w,]+))?\s*(?:as (\w+))?\s*(?:prefix (\w+))?(?::(cols|params))?\}",
+    )
+    .unwrap();
+
+    let placeholders = match find_placeholders(&text, &re, &text_literal) {
+        Ok(placeholders) => placeholders,
+        Err(e) => return e.to_compile_error().into(),
     };
 
-    let re = Regex::new(r"\{([^\}:\s]+)(?:::([\w,]+))?\s*(?:as (\w+))?\}").unwrap();
+    // `{Model:params}` is numbered after the query's own parameters, and after any earlier
+    // `{Model:params}`, whose lengths are only known once the query runs.
+    let existing_params = Regex::new(r"\$(\d+)")
+        .unwrap()
+        .captures_iter(&text)
+        .filter_map(|cap| cap[1].parse::<usize>().ok())
+        .max()
+        .unwrap_or(0);
+    let mut next_param = quote! { #existing_params };
+    let mut param_starts = HashMap::new();
+    for cap in placeholders.iter() {
+        if cap.get(5).map(|kind| kind.as_str()) == Some("params") {
+            // The values of `{Model:params}` are passed separately, so they can't be put in order
+            // with the named arguments.
+            if !params.is_empty() {
+                return syn::Error::new(
+                    text_literal.span(),
+                    format!(
+                        "`{}` can't be used with named arguments in `query!`, name each value instead.",
+                        &cap[0]
+                    ),
+                )
+                .to_compile_error()
+                .into();
+            }
+
+            let model_ident = match parse_model(&cap[1], &text_literal) {
+                Ok(model_ident) => model_ident,
+                Err(e) => return e.to_compile_error().into(),
+            };
+            let (field_names, excluded) = selected_fields(cap.get(2));
+            let count = param_count(&model_ident, &field_names, excluded);
+
+            param_starts.insert(cap.get(0).unwrap().start(), next_param.clone());
+            next_param = quote! { #next_param + #count };
+        }
+    }
 
-    let mut matches = re.find_iter(&text).into_iter().collect::<Vec<Match>>();
-    matches.reverse();
     let mut output_string = text.clone();
     let mut value_injections = vec![];
+    let mut alias_checks = vec![];
 
-    for m in matches {
-        for cap in re.captures_iter(m.as_str()) {
+    for cap in placeholders.iter().rev() {
+        let m = cap.get(0).unwrap();
+        {
             let mut cap_iter = cap.iter();
             let _full = cap_iter.next().unwrap().unwrap().as_str();
 
             let model_name = cap_iter.next().unwrap().unwrap().as_str();
-            let field_names = match cap_iter.next() {
-                Some(Some(inner_match)) => {
-                    let value = inner_match.as_str();
+            let (field_names, excluded) = selected_fields(cap_iter.next().flatten());
 
-                    value.split(",").collect::<Vec<&str>>()
+            let table_name = match cap_iter.next() {
+                Some(Some(inner_match)) => {
+                    let table_name = Literal::string(inner_match.as_str());
+                    quote! { Some(#table_name) }
                 }
-                _ => vec![],
+                _ => quote! { None },
             };
 
-            let table_name = match cap_iter.next() {
-                Some(Some(inner_match)) => Some(inner_match.as_str()),
-                _ => None,
+            let model_ident = match parse_model(model_name, &text_literal) {
+                Ok(model_ident) => model_ident,
+                Err(e) => return e.to_compile_error().into(),
+            };
+            let named_prefix = cap_iter
+                .next()
+                .flatten()
+                .map(|inner_match| Literal::string(&named_prefix(inner_match.as_str())));
+            let prefix = match &named_prefix {
+                Some(prefix) => quote! { Some(#prefix) },
+                None => quote! { Some(<#model_ident>::prefix()) },
             };
+            let kind = cap_iter.next().flatten().map(|kind| kind.as_str());
+
+            // The derive already checked the aliases under the model's own prefix.
+            if let (Some(prefix), None) = (&named_prefix, kind) {
+                alias_checks.push(quote! {
+                    const _: () = ::usual::base::check_aliases(#prefix, <#model_ident as ::usual::base::Model>::COLUMNS);
+                });
+            }
 
-            if field_names.len() > 0 {
-                let mut fields = vec![];
-
-                let _ = &field_names.into_iter().for_each(|f| {
-                    let model_ident = Ident::new(model_name, Span2::call_site());
-                    let column_literal = Literal::string(f);
-
-                    match table_name {
-                        Some(table_name) => {
-                            let table_name = Literal::string(table_name);
-                            fields.push(quote! { <#model_ident>::column_with_prefix_and_table(#column_literal, Some(<#model_ident>::prefix()), Some(#table_name)) });
-                        }
-                        None => {
-                            fields.push(quote! { <#model_ident>::column_with_prefix_and_table(#column_literal, Some(<#model_ident>::prefix()), None) });
-                        }
+            // A bare column list, for `INSERT INTO table (...)`, has no aliases.
+            let prefix = match kind {
+                Some("cols") => quote! { None },
+                _ => prefix,
+            };
+
+            let initial_injection_count = value_injections.len();
+
+            if kind == Some("params") {
+                let first = &param_starts[&m.start()];
+                let count = param_count(&model_ident, &field_names, excluded);
+
+                value_injections.push(quote! {
+                    ::usual::base::placeholders(#first + 1, #count)
+                });
+            } else if excluded {
+                // Naming each excluded field's column accessor makes a misspelled field a compile
+                // error, the same as for included fields.
+                let excluded_columns = field_names.into_iter().map(|f| {
+                    let column_name = column_name_ident(&Ident::new(f, Span2::call_site()));
+                    quote! { <#model_ident>::#column_name() }
+                });
+
+                value_injections.push(quote! {
+                    {
+                        let excluded = [#( #excluded_columns ),*];
+
+                        <#model_ident>::columns_list()
+                            .into_iter()
+                            .filter(|column| !excluded.contains(column))
+                            .map(|column| <#model_ident>::column_with_prefix_and_table(column, #prefix, #table_name))
+                            .collect::<Vec<String>>()
+                            .join(", ")
                     }
                 });
+            } else if !field_names.is_empty() {
+                let mut fields = field_names
+                    .into_iter()
+                    .map(|f| {
+                        let column_name = column_name_ident(&Ident::new(f, Span2::call_site()));
+                        quote! { <#model_ident>::column_with_prefix_and_table(<#model_ident>::#column_name(), #prefix, #table_name) }
+                    })
+                    .collect::<Vec<_>>();
 
                 // Reverse the fields order since we're going back to front for the matches -- they'll get switched when we reverse the whole array.
                 fields.reverse();
                 value_injections.append(&mut fields);
-
-                output_string.replace_range(
-                    m.range(),
-                    &std::iter::repeat("{}")
-                        .take(value_injections.len())
-                        .collect::<Vec<&str>>()
-                        .join(", "),
-                );
             } else {
-                let ident = Ident::new(model_name, Span2::call_site());
-                let initial_injection_count = value_injections.len();
-
-                match table_name {
-                    Some(table_name) => {
-                        let table_name = Literal::string(table_name);
-                        value_injections.push(quote! { <#ident>::columns_with_table(#table_name) });
-                    }
-                    None => {
-                        value_injections.push(quote! { <#ident>::columns() });
-                    }
-                }
-
-                output_string.replace_range(
-                    m.range(),
-                    &std::iter::repeat("{}")
-                        .take(value_injections.len() - initial_injection_count)
-                        .collect::<Vec<&str>>()
-                        .join(", "),
+                value_injections.push(
+                    quote! { <#model_ident>::columns_with_prefix_and_table(#prefix, #table_name) },
                 );
             }
+
+            output_string.replace_range(
+                m.range(),
+                &std::iter::repeat_n("{}", value_injections.len() - initial_injection_count)
+                    .collect::<Vec<&str>>()
+                    .join(", "),
+            );
         }
     }
 
     value_injections.reverse();
 
-    let gen = quote! {
-        format!(#output_string, #( #value_injections,)*)
+    // Without named arguments, a `:name` in the query is left for the database, like an array
+    // slice `arr[1:n]` or a psql variable.
+    let output_string = if params.is_empty() {
+        output_string
+    } else {
+        match replace_named_params(&output_string, &text_literal, &params) {
+            Ok(output_string) => output_string,
+            Err(e) => return e.to_compile_error().into(),
+        }
+    };
+
+    let track_separator = track_separator();
+    let gen = if params.is_empty() {
+        quote! {
+            {
+                #track_separator
+                #( #alias_checks )*
+                format!(#output_string, #( #value_injections,)*)
+            }
+        }
+    } else {
+        let values = params.iter().map(|param| &param.value);
+
+        quote! {
+            {
+                #track_separator
+                #( #alias_checks )*
+                ::usual::write::Statement {
+                    sql: format!(#output_string, #( #value_injections,)*),
+                    params: vec![#({
+                        let param: ::usual::base::SqlParam<'_> = #values;
+                        param
+                    }),*],
+                }
+            }
+        }
     };
 
     // proc_macro::Span::call_site()
@@ -116,107 +226,554 @@ pub fn query(items: TokenStream) -> TokenStream {
     gen.into()
 }
 
-struct Field {
-    name: String,
-    ty: String,
+fn parse_model(model_name: &str, literal: &LitStr) -> syn::Result<Type> {
+    syn::parse_str::<Type>(model_name).map_err(|_| {
+        syn::Error::new(
+            literal.span(),
+            format!("`{}` in `query!` is not a valid model name.", model_name),
+        )
+    })
 }
 
-#[proc_macro]
-pub fn partial(items: TokenStream) -> TokenStream {
-    let items = proc_macro2::TokenStream::from(items);
-    let mut item_iter = items.clone().into_iter();
+/// The placeholders in the query, in order. Every `{` has to start a placeholder `query!`
+/// understands, or be escaped as `{{`, so a malformed placeholder is reported as such rather than
+/// as a broken format string. Escaped braces are left for `format!`.
+fn find_placeholders<'t>(
+    text: &'t str,
+    re: &Regex,
+    literal: &LitStr,
+) -> syn::Result<Vec<Captures<'t>>> {
+    let mut placeholders = vec![];
+    let mut i = 0;
 
-    let model = match item_iter.next() {
-        Some(TokenTree2::Ident(ident)) => ident,
-        _ => panic!("The first argument of `query!` must be a string literal."),
-    };
-    let model_name = model.to_string();
-    let partial_ident = Ident::new(&format!("Partial{}", model.to_string()), Span2::call_site());
-    let partial_ident_name = partial_ident.to_string();
+    while let Some(start) = text[i..].find(['{', '}']).map(|offset| i + offset) {
+        let brace = &text[start..start + 1];
+
+        if text[start + 1..].starts_with(brace) {
+            i = start + 2;
+        } else if let Some(cap) = re
+            .captures_at(text, start)
+            .filter(|cap| cap.get(0).unwrap().start() == start)
+        {
+            i = cap.get(0).unwrap().end();
+            placeholders.push(cap);
+        } else {
+            let end = text[start..]
+                .find('}')
+                .map_or(text.len(), |offset| start + offset + 1);
+
+            return Err(syn::Error::new(
+                literal.span(),
+                format!(
+                    "`{}` in `query!` is not a placeholder, like `{{Post}}`, `{{Post::title,content as p}}` or `{{Record<i64, String>}}`. Write `{}{}` for a literal `{}`.",
+                    &text[start..end],
+                    brace,
+                    brace,
+                    brace
+                ),
+            ));
+        }
+    }
+
+    Ok(placeholders)
+}
+
+/// The fields named in a `query!` placeholder, like `title,content`, and whether they're
+/// excluded, like `!body`.
+fn selected_fields(fields: Option<Match<'_>>) -> (Vec<&str>, bool) {
+    match fields {
+        Some(fields) => {
+            let value = fields.as_str();
+
+            match value.strip_prefix('!') {
+                Some(excluded) => (excluded.split(",").collect::<Vec<&str>>(), true),
+                None => (value.split(",").collect::<Vec<&str>>(), false),
+            }
+        }
+        None => (vec![], false),
+    }
+}
+
+/// How many columns a `query!` placeholder selects.
+fn param_count(model_ident: &Type, field_names: &[&str], excluded: bool) -> TokenStream2 {
+    let count = field_names.len();
+
+    match (count, excluded) {
+        (0, _) => quote! { <#model_ident>::columns_list().len() },
+        (_, true) => quote! { <#model_ident>::columns_list().len() - #count },
+        (_, false) => quote! { #count },
+    }
+}
+
+/// The arguments to `query!`: the query, then a value for each `:name` parameter in it.
+struct QueryInput {
+    text: LitStr,
+    params: Vec<NamedParam>,
+}
+
+struct NamedParam {
+    name: Ident,
+    value: syn::Expr,
+}
+
+impl Parse for QueryInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let text = input.parse::<LitStr>().map_err(|e| {
+            syn::Error::new(
+                e.span(),
+                "The first argument of `query!` must be a string literal.",
+            )
+        })?;
+
+        let mut params: Vec<NamedParam> = vec![];
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+
+            let name = input.parse::<Ident>()?;
+            input.parse::<Token![=]>().map_err(|e| {
+                syn::Error::new(
+                    e.span(),
+                    "A parameter must be given as `name = value`, for example, `author = &author`.",
+                )
+            })?;
+            let value = input.parse::<syn::Expr>()?;
+
+            if params.iter().any(|param| param.name == name) {
+                return Err(syn::Error::new(
+                    name.span(),
+                    format!("`{}` is given more than once.", name),
+                ));
+            }
+
+            params.push(NamedParam { name, value });
+        }
+
+        Ok(QueryInput { text, params })
+    }
+}
 
-    // Accept more arguments -- really not sure if we need this.
-    let mut fields = vec![];
+/// Replaces each `:name` in the query with its positional placeholder, in the order of `params`.
+/// A name used more than once gets the same placeholder each time.
+fn replace_named_params(
+    text: &str,
+    literal: &LitStr,
+    params: &[NamedParam],
+) -> syn::Result<String> {
+    let mut output = String::with_capacity(text.len());
+    let mut used = vec![false; params.len()];
+    let mut rest = text;
 
-    item_iter.next(); // skip punctuation
+    while let Some((before, param, after)) = next_param(rest) {
+        output.push_str(before);
 
-    while let Some(ident) = item_iter.next() {
-        let field_name = match ident {
-            TokenTree2::Ident(i) => i,
-            _ => panic!("All arguments after the first must be identifiers."),
+        let name = match param {
+            Param::Named(name) => name,
+            Param::Positional(placeholder) => {
+                return Err(syn::Error::new(
+                    literal.span(),
+                    format!(
+                        "`{}` in `query!` can't be mixed with named arguments, name it too.",
+                        placeholder
+                    ),
+                ))
+            }
         };
+        let index = params
+            .iter()
+            .position(|param| param.name == name)
+            .ok_or_else(|| {
+                syn::Error::new(
+                    literal.span(),
+                    format!("`:{}` in `query!` has no matching argument.", name),
+                )
+            })?;
+        used[index] = true;
+        output.push_str(&format!("${}", index + 1));
+
+        rest = after;
+    }
+    output.push_str(rest);
 
-        match item_iter.next() {
-            Some(TokenTree2::Ident(i)) => if i.to_string() != "as" { panic!("A field identifier must be followed by `as Type`, for example, `content as String`.") },
-            _ => panic!("A field identifier must be followed by `as Type`, for example, `content as String`."),
-        }; // skip punctuation
+    if let Some(unused) = params.iter().zip(used).find(|(_, used)| !used) {
+        return Err(syn::Error::new(
+            unused.0.name.span(),
+            format!("`{}` is not used in the query.", unused.0.name),
+        ));
+    }
+
+    Ok(output)
+}
+
+/// A parameter in the text of a query.
+enum Param<'t> {
+    /// `:name`, without the colon.
+    Named(&'t str),
+    /// `$n`, with the dollar sign.
+    Positional(&'t str),
+}
 
-        let ty = match item_iter.next() {
-            Some(TokenTree2::Ident(i)) => {
-                i
-            },
-            _ => panic!("A field identifier must be followed by `as Type`, for example, `content as String`."),
+/// Finds the next `:name` or `$n` in `text`, returning the text before it, the parameter, and the
+/// text after. Quoted strings and identifiers, dollar-quoted strings, comments, `::` casts, and
+/// array slices like `arr[lo:hi]` are skipped, the same way `usual::dialect::Dialect::rewrite`
+/// skips them at runtime.
+fn next_param(text: &str) -> Option<(&str, Param<'_>, &str)> {
+    let mut brackets = 0usize;
+    let mut i = 0;
+
+    while let Some(c) = text[i..].chars().next() {
+        let rest = &text[i..];
+
+        let len = match c {
+            '\'' | '"' => quoted_len(rest, 1, &rest[..1]),
+            '-' if rest.starts_with("--") => rest.find('\n').unwrap_or(rest.len()),
+            '/' if rest.starts_with("/*") => block_comment_len(rest),
+            '$' if rest[1..].starts_with(|c: char| c.is_ascii_digit()) => {
+                let end = 1 + rest[1..]
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len() - 1);
+
+                return Some((&text[..i], Param::Positional(&rest[..end]), &rest[end..]));
+            }
+            // A `$` inside an identifier, like `a$b`, doesn't start a dollar-quoted string.
+            '$' if !text[..i].ends_with(|c: char| c.is_alphanumeric() || c == '_') => {
+                dollar_quote_tag_len(rest)
+                    .map_or(1, |tag_len| quoted_len(rest, tag_len, &rest[..tag_len]))
+            }
+            '[' => {
+                brackets += 1;
+                1
+            }
+            ']' => {
+                brackets = brackets.saturating_sub(1);
+                1
+            }
+            ':' if rest.starts_with("::") => 2,
+            ':' if brackets == 0
+                && rest[1..].starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') =>
+            {
+                let end = 1 + rest[1..]
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(rest.len() - 1);
+
+                return Some((&text[..i], Param::Named(&rest[1..end]), &rest[end..]));
+            }
+            c => c.len_utf8(),
+        };
+
+        i += len;
+    }
+
+    None
+}
+
+// The scanner below matches `usual::dialect`'s, which this crate can't depend on.
+
+/// The length of a quoted string at the start of `sql`, from its opening quote of `open` bytes to
+/// the end of its closing quote, or the end of `sql` if it isn't closed.
+fn quoted_len(sql: &str, open: usize, close: &str) -> usize {
+    sql[open..]
+        .find(close)
+        .map_or(sql.len(), |i| open + i + close.len())
+}
+
+/// The length of the `/* comment */` at the start of `sql`. Like in Postgres, comments nest.
+fn block_comment_len(sql: &str) -> usize {
+    let mut depth = 0;
+    let mut i = 0;
+
+    while i < sql.len() {
+        if sql[i..].starts_with("/*") {
+            depth += 1;
+            i += 2;
+        } else if sql[i..].starts_with("*/") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += sql[i..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+
+    sql.len()
+}
+
+/// The length of the tag of a dollar-quoted string at the start of `sql`, like `$$` or `$body$`.
+fn dollar_quote_tag_len(sql: &str) -> Option<usize> {
+    let name_len = sql[1..]
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(sql.len() - 1);
+
+    sql[1 + name_len..].starts_with('$').then_some(name_len + 2)
+}
+
+struct PartialField {
+    name: Ident,
+    ty: Type,
+}
+
+impl Parse for PartialField {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = input.parse::<Ident>()?;
+        input.parse::<Token![as]>().map_err(|e| {
+            syn::Error::new(
+                e.span(),
+                "A field identifier must be followed by `as Type`, for example, `content as String`.",
+            )
+        })?;
+        let ty = input.parse::<Type>()?;
+
+        Ok(PartialField { name, ty })
+    }
+}
+
+struct SerdeOptions {
+    skip: bool,
+    rename_all: LitStr,
+}
+
+impl Default for SerdeOptions {
+    fn default() -> Self {
+        SerdeOptions {
+            skip: false,
+            rename_all: LitStr::new("camelCase", Span2::call_site()),
+        }
+    }
+}
+
+impl Parse for SerdeOptions {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut options = SerdeOptions::default();
+
+        while !input.is_empty() {
+            let option = input.parse::<Ident>()?;
+
+            if option == "skip" {
+                options.skip = true;
+            } else if option == "rename_all" {
+                input.parse::<Token![=]>()?;
+                options.rename_all = input.parse::<LitStr>()?;
+            } else {
+                return Err(syn::Error::new(
+                    option.span(),
+                    "Serde options must be either `skip` or `rename_all = \"...\"`.",
+                ));
+            }
+
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+impl SerdeOptions {
+    /// The attributes to put on a generated struct. Nothing is emitted when `usual` is built
+    /// without its `serde` feature. Serde is reached through `usual`'s re-export, so the crate
+    /// using the macro doesn't need its own `serde` dependency.
+    fn attributes(&self) -> TokenStream2 {
+        if self.skip || !cfg!(feature = "serde") {
+            return quote! {};
+        }
+
+        let rename_all = &self.rename_all;
+        quote! {
+            #[derive(::usual::serde::Deserialize, ::usual::serde::Serialize)]
+            #[serde(crate = "::usual::serde", rename_all = #rename_all)]
+        }
+    }
+}
+
+struct PartialInput {
+    serde: SerdeOptions,
+    model: syn::Path,
+    prefix: Option<Ident>,
+    fields: Vec<PartialField>,
+}
+
+impl Parse for PartialInput {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let serde = if input.peek(syn::Ident) && input.peek2(token::Paren) {
+            let keyword = input.parse::<Ident>()?;
+            if keyword != "serde" {
+                return Err(syn::Error::new(
+                    keyword.span(),
+                    "Only `serde(...)` options can come before the model name.",
+                ));
+            }
+
+            let content;
+            parenthesized!(content in input);
+            content.parse::<SerdeOptions>()?
+        } else {
+            SerdeOptions::default()
+        };
+
+        let model = input.parse::<syn::Path>().map_err(|e| {
+            syn::Error::new(
+                e.span(),
+                "The first argument of `partial!` must be the name of a model.",
+            )
+        })?;
+
+        let prefix = if input.peek(syn::Ident) {
+            let keyword = input.parse::<Ident>()?;
+            if keyword != "prefix" {
+                return Err(syn::Error::new(
+                    keyword.span(),
+                    "A model name can only be followed by `prefix name`, for example, `Post prefix parent`.",
+                ));
+            }
+
+            Some(input.parse::<Ident>()?)
+        } else {
+            None
         };
 
-        fields.push(Field {
-            name: field_name.to_string(),
-            ty: ty.to_string(),
-        });
+        let mut fields = vec![];
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
 
-        item_iter.next(); // skip punctuation
+            if input.is_empty() {
+                break;
+            }
+
+            fields.push(input.parse::<PartialField>()?);
+        }
+
+        Ok(PartialInput {
+            serde,
+            model,
+            prefix,
+            fields,
+        })
     }
+}
+
+#[proc_macro]
+pub fn partial(items: TokenStream) -> TokenStream {
+    let PartialInput {
+        serde,
+        model,
+        prefix,
+        fields,
+    } = parse_macro_input!(items as PartialInput);
+
+    let model_name = model.segments.last().unwrap().ident.to_string();
+    let (prefix, alias_check) = match prefix {
+        Some(prefix) => {
+            let prefix = named_prefix(&prefix.to_string());
+            let alias_check = quote! {
+                const _: () = ::usual::base::check_aliases(#prefix, <#model as ::usual::base::Model>::COLUMNS);
+            };
+
+            (quote! { #prefix }, alias_check)
+        }
+        None => (quote! { <#model>::prefix() }, quote! {}),
+    };
+    let track_separator = track_separator();
+    let partial_ident = Ident::new(&format!("Partial{}", model_name), Span2::call_site());
+    let partial_ident_name = partial_ident.to_string();
 
     let field_declarations = fields
         .iter()
         .map(|f| {
-            let field_name = Ident::new(&f.name, Span2::call_site());
-            let field_type = Ident::new(&f.ty, Span2::call_site());
+            let field_name = &f.name;
+            let field_type = &f.ty;
             quote! {
                 pub #field_name: #field_type
             }
         })
         .collect::<Vec<_>>();
 
+    // Every field must exist on the parent model with exactly the declared type. `UsualModel`
+    // emits a hidden `__usual_field_*` accessor per column for this, so a typo or a mismatched
+    // type is a compile error rather than a failed `try_get` at runtime.
+    let field_checks = fields
+        .iter()
+        .map(|f| {
+            let checker = field_checker_ident(&f.name);
+            let field_type = &f.ty;
+            quote_spanned! { field_type.span() =>
+                let _: ::std::marker::PhantomData<#field_type> = <#model>::#checker();
+            }
+        })
+        .collect::<Vec<_>>();
+
     let field_initializers = fields
         .iter()
         .map(|f| {
-            let field_name = Ident::new(&f.name, Span2::call_site());
+            let field_name = &f.name;
             let field_key = field_name.to_string();
+            let column_name = column_name_ident(field_name);
             quote! {
-                #field_name: row.try_get(format!("{}{}", Self::prefix(), #field_key).as_str())
-                .expect(&format!("You messed up while trying to get {} ({}{}) from {}", #field_key, Self::prefix(), #field_key, #partial_ident_name))
+                #field_name: row.try_get(::usual::base::alias(prefix, <#model>::#column_name()).as_str())
+                .map_err(|e| ::usual::base::column_error(e, #field_key, prefix, <#model>::#column_name(), #partial_ident_name))?
             }
         })
         .collect::<Vec<_>>();
 
-    let field_keys = fields.iter().map(|f| f.name.clone()).collect::<Vec<_>>();
+    let field_keys = fields
+        .iter()
+        .map(|f| f.name.to_string())
+        .collect::<Vec<_>>();
+    let field_columns = fields
+        .iter()
+        .map(|f| {
+            let column_name = column_name_ident(&f.name);
+            quote! { <#model>::#column_name() }
+        })
+        .collect::<Vec<_>>();
+    let serde_attributes = serde.attributes();
 
     let gen = quote! {
         |r| {
-            #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
-            #[serde(rename_all = "camelCase")]
+            #[allow(dead_code)]
+            fn __usual_check_fields() {
+                #( #field_checks )*
+            }
+
+            #track_separator
+            #alias_check
+
+            #serde_attributes
+            #[derive(Clone, Debug)]
             struct #partial_ident {
                 #(
                     #field_declarations
                 ),*
-            };
+            }
+
+            impl<'r> ::usual::base::ModelRef<'r> for #partial_ident {
+                fn try_from_row_ref_with_prefix<R: TryGetRow>(prefix: &str, row: &'r R) -> Result<Self, ::usual::base::Error> {
+                    Ok(#partial_ident {
+                        #( #field_initializers ),*
+                    })
+                }
+            }
 
             impl Model for #partial_ident {
                 fn prefix() -> &'static str {
-                    concat!(#model_name, "__")
+                    #prefix
                 }
 
-                fn from_row_starting_index(_index: usize, row: &impl TryGetRow) -> Self {
-                    #partial_ident {
-                        #( #field_initializers ),*
-                    }
+                fn columns_list() -> Vec<&'static str> {
+                    vec![#( #field_columns ),*]
                 }
 
-                fn columns_list() -> Vec<&'static str> {
+                fn fields_list() -> Vec<&'static str> {
                     vec![#( #field_keys ),*]
                 }
+
             }
 
-            #partial_ident::from_row_starting_index(0, r)
+            #partial_ident::from_row(r)
         }
     };
 
@@ -228,75 +785,568 @@ pub fn partial(items: TokenStream) -> TokenStream {
     gen.into()
 }
 
-#[proc_macro_derive(UsualModel, attributes(unusual))]
+/// Adds serde derives to a struct according to `usual`'s `serde` feature, for example,
+/// `#[usual_serde(rename_all = "snake_case")]`. With no options the fields are renamed to
+/// camelCase, and `#[usual_serde(skip)]` leaves the struct alone.
+#[proc_macro_attribute]
+pub fn usual_serde(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let options = parse_macro_input!(attr as SerdeOptions);
+    let attributes = options.attributes();
+    let item = TokenStream2::from(item);
+
+    let gen = quote! {
+        #attributes
+        #item
+    };
+
+    gen.into()
+}
+
+/// The prefix for a name: a model's own name or `#[usual(prefix = "...")]`, or a `prefix name`
+/// in `query!` and `partial!`. Every prefix ends with the separator.
+fn named_prefix(name: &str) -> String {
+    format!("{}{}", name, separator())
+}
+
+/// What goes between a name and a column in an alias: `__`, unless `USUAL_SEPARATOR` is set in
+/// the environment of the build.
+fn separator() -> String {
+    std::env::var("USUAL_SEPARATOR").unwrap_or_else(|_| "__".to_string())
+}
+
+/// Makes the crate being built depend on `USUAL_SEPARATOR`, so changing it rebuilds the crate
+/// instead of leaving it with the old separator.
+fn track_separator() -> TokenStream2 {
+    quote! {
+        const _: Option<&str> = option_env!("USUAL_SEPARATOR");
+    }
+}
+
+/// Whether any of `idents` appears anywhere in `tokens`.
+fn mentions_any(tokens: TokenStream2, idents: &[Ident]) -> bool {
+    tokens.into_iter().any(|token| match token {
+        TokenTree2::Ident(ident) => idents.contains(&ident),
+        TokenTree2::Group(group) => mentions_any(group.stream(), idents),
+        _ => false,
+    })
+}
+
+/// The hidden associated function `UsualModel` generates for each column, used by `partial!` to
+/// check that a field exists on the model and has the expected type.
+fn field_checker_ident(field: &Ident) -> Ident {
+    Ident::new(&format!("__usual_field_{}", field), field.span())
+}
+
+/// The hidden associated function `UsualModel` generates for each column, returning the column's
+/// name, so `query!` and `partial!` pick up `#[usual(rename = "...")]`.
+fn column_name_ident(field: &Ident) -> Ident {
+    Ident::new(&format!("__usual_column_{}", field), field.span())
+}
+
+/// How a field maps to its column, from `#[usual(...)]`.
+#[derive(Default)]
+struct UsualOptions {
+    rename: Option<LitStr>,
+    primary_key: bool,
+    generated: bool,
+    db_default: bool,
+    quote: bool,
+}
+
+impl UsualOptions {
+    fn from_field(field: &syn::Field) -> syn::Result<Self> {
+        let mut options = UsualOptions::default();
+
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path.is_ident("usual"))
+        {
+            let metas =
+                attr.parse_args_with(Punctuated::<syn::Meta, Token![,]>::parse_terminated)?;
+            for meta in metas {
+                match meta {
+                    syn::Meta::NameValue(syn::MetaNameValue {
+                        path,
+                        lit: syn::Lit::Str(lit),
+                        ..
+                    }) if path.is_ident("rename") => options.rename = Some(lit),
+                    syn::Meta::Path(path) if path.is_ident("primary_key") => {
+                        options.primary_key = true
+                    }
+                    syn::Meta::Path(path) if path.is_ident("generated") => {
+                        options.generated = true
+                    }
+                    syn::Meta::Path(path) if path.is_ident("db_default") => {
+                        options.db_default = true
+                    }
+                    syn::Meta::Path(path) if path.is_ident("quote") => options.quote = true,
+                    meta => {
+                        return Err(syn::Error::new(
+                            meta.span(),
+                            "`#[usual]` only accepts `rename = \"column\"`, `primary_key`, `generated`, `db_default` and `quote`.",
+                        ))
+                    }
+                }
+            }
+        }
+
+        Ok(options)
+    }
+
+    /// Whether the column is left out of generated inserts, so the database fills it in. Being
+    /// part of the primary key doesn't matter, since natural and composite keys are written.
+    fn generated_by_db(&self) -> bool {
+        self.generated || self.db_default
+    }
+
+    /// The name of the column the field is stored in.
+    fn column(&self, field: &Ident) -> LitStr {
+        self.rename
+            .clone()
+            .unwrap_or_else(|| LitStr::new(&field.to_string(), field.span()))
+    }
+}
+
+/// Options for the whole model, from `#[usual(...)]` on the struct.
+#[derive(Default)]
+struct ModelOptions {
+    prefix: Option<LitStr>,
+    quote: bool,
+}
+
+impl ModelOptions {
+    fn from_attrs(attrs: &[syn::Attribute]) -> syn::Result<Self> {
+        let mut options = ModelOptions::default();
+
+        for attr in attrs.iter().filter(|attr| attr.path.is_ident("usual")) {
+            let metas =
+                attr.parse_args_with(Punctuated::<syn::Meta, Token![,]>::parse_terminated)?;
+            for meta in metas {
+                match meta {
+                    syn::Meta::NameValue(syn::MetaNameValue {
+                        path,
+                        lit: syn::Lit::Str(lit),
+                        ..
+                    }) if path.is_ident("prefix") => options.prefix = Some(lit),
+                    syn::Meta::Path(path) if path.is_ident("quote") => options.quote = true,
+                    meta => return Err(syn::Error::new(
+                        meta.span(),
+                        "`#[usual]` on a struct only accepts `prefix = \"prefix\"` and `quote`.",
+                    )),
+                }
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// How an `#[unusual]` field is filled in, since it isn't read from the row.
+#[derive(Default)]
+struct UnusualOptions {
+    default: Option<syn::Path>,
+    compute: Option<Compute>,
+}
+
+/// How an `#[unusual(compute = "...")]` field is computed.
+enum Compute {
+    /// `compute = "path::to::fn"`, a `fn(&Self) -> T` called once the rest of the model is built.
+    Model(syn::Path),
+    /// `compute = "f(title, ...)"`, called with the named columns as soon as they're read.
+    Columns(syn::ExprCall),
+}
+
+impl UnusualOptions {
+    fn from_field(field: &syn::Field) -> syn::Result<Self> {
+        let mut options = UnusualOptions::default();
+
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path.is_ident("unusual"))
+        {
+            if attr.tokens.is_empty() {
+                continue;
+            }
+
+            let values = attr
+                .parse_args_with(Punctuated::<syn::MetaNameValue, Token![,]>::parse_terminated)?;
+            for value in values {
+                let lit = match &value.lit {
+                    syn::Lit::Str(lit) => lit,
+                    lit => {
+                        return Err(syn::Error::new(
+                            lit.span(),
+                            "Expected a string, for example, `default = \"path::to::fn\"`.",
+                        ))
+                    }
+                };
+
+                if value.path.is_ident("default") {
+                    options.default = Some(lit.parse::<syn::Path>()?);
+                } else if value.path.is_ident("compute") {
+                    let compute = match lit.parse::<syn::Path>() {
+                        Ok(path) => Compute::Model(path),
+                        Err(_) => Compute::Columns(lit.parse::<syn::ExprCall>().map_err(|e| {
+                            syn::Error::new(
+                                e.span(),
+                                "`compute` must name a `fn(&Self) -> T`, like `compute = \"Post::slug\"`, or call a function with the fields it's computed from, like `compute = \"slug(title)\"`.",
+                            )
+                        })?),
+                    };
+                    options.compute = Some(compute);
+                } else {
+                    return Err(syn::Error::new(
+                        value.path.span(),
+                        "`#[unusual]` only accepts `default = \"...\"` and `compute = \"...\"`.",
+                    ));
+                }
+            }
+        }
+
+        if options.default.is_some() && matches!(options.compute, Some(Compute::Columns(_))) {
+            return Err(syn::Error::new(
+                field.span(),
+                "An `#[unusual]` field computed from its columns can't also have a `default` function.",
+            ));
+        }
+
+        Ok(options)
+    }
+
+    /// The expression the field is initialized with. A `compute` call gets a reference to each
+    /// field it names, which must be one of the model's columns. A field computed from the model
+    /// starts out with its default, until [`UnusualOptions::finisher`] replaces it.
+    fn initializer(&self, fields: &[Ident], values: &[Ident]) -> syn::Result<TokenStream2> {
+        let compute = match (&self.default, &self.compute) {
+            (_, Some(Compute::Columns(compute))) => compute,
+            (Some(default), _) => return Ok(quote! { #default() }),
+            (None, _) => return Ok(quote! { Default::default() }),
+        };
+
+        let func = &compute.func;
+        let args = compute
+            .args
+            .iter()
+            .map(|arg| {
+                let index = match arg {
+                    syn::Expr::Path(path) => path
+                        .path
+                        .get_ident()
+                        .and_then(|ident| fields.iter().position(|field| field == ident)),
+                    _ => None,
+                };
+
+                match index {
+                    Some(index) => {
+                        let value = &values[index];
+                        Ok(quote! { &#value })
+                    }
+                    None => Err(syn::Error::new(
+                        arg.span(),
+                        "The arguments of `compute` must be fields of the model read from the row.",
+                    )),
+                }
+            })
+            .collect::<syn::Result<Vec<_>>>()?;
+
+        Ok(quote! { #func(#( #args ),*) })
+    }
+
+    /// Sets a field computed from the model, once the rest of it is built.
+    fn finisher(&self, field: &Ident) -> Option<TokenStream2> {
+        match &self.compute {
+            Some(Compute::Model(compute)) => Some(quote! { model.#field = #compute(&model); }),
+            _ => None,
+        }
+    }
+}
+
+#[proc_macro_derive(UsualModel, attributes(usual, unusual))]
 pub fn usual_model_macro_derive(items: TokenStream) -> TokenStream {
     let ast: syn::DeriveInput = syn::parse(items).unwrap();
 
-    let name = Ident::from(ast.ident);
+    let model_options = match ModelOptions::from_attrs(&ast.attrs) {
+        Ok(options) => options,
+        Err(e) => return e.to_compile_error().into(),
+    };
+    let name = ast.ident;
+    let prefix = match &model_options.prefix {
+        Some(prefix) => named_prefix(&prefix.value()),
+        None => named_prefix(&name.to_string()),
+    };
+    let mut generics = ast.generics;
     let (fields, skipped) = match ast.data {
         syn::Data::Struct(data_struct) => match data_struct.fields {
             syn::Fields::Named(named_fields) => {
-                let (skipped, fields): (Vec<syn::Field>, Vec<syn::Field>) =
+                let (fields, skipped): (Vec<syn::Field>, Vec<syn::Field>) =
                     named_fields.named.into_iter().partition(|field| {
-                        !field.attrs.iter().any(|attr| {
-                            attr.path.segments.first().unwrap().ident.to_string() == "unusual"
-                        })
+                        !field
+                            .attrs
+                            .iter()
+                            .any(|attr| attr.path.segments.first().unwrap().ident == "unusual")
                     });
 
-                (
-                    skipped
-                        .into_iter()
-                        .map(|field| Ident::from(field.ident.unwrap()))
-                        .collect::<Vec<Ident>>(),
-                    fields
-                        .into_iter()
-                        .map(|field| Ident::from(field.ident.unwrap()))
-                        .collect::<Vec<Ident>>(),
-                )
+                (fields, skipped)
             }
             _ => panic!("Can only derive named fields of struct"),
         },
         _ => panic!("Can only derive fields of struct"),
     };
 
-    let gen = quote! {
-        impl Model for #name {
-            fn from_row_starting_index(_index: usize, row: &impl TryGetRow) -> Self {
-              #name {
-                #(
-                    #fields: row.try_get(format!("{}{}", Self::prefix(), stringify!(#fields)).as_str())
-                    .expect(&format!("You messed up while trying to get {} ({}{}) from {}", stringify!(#fields), Self::prefix(), stringify!(#fields), stringify!(#name)))
-                ),*,
-                #(
-                    #skipped: Default::default()
-                ),*
+    let mut columns = vec![];
+    let mut insert_columns = vec![];
+    let mut primary_key_columns = vec![];
+    let mut primary_key_fields = vec![];
+    let mut quoted_columns = vec![];
+    for field in fields.iter() {
+        let options = match UsualOptions::from_field(field) {
+            Ok(options) => options,
+            Err(e) => return e.to_compile_error().into(),
+        };
+
+        let column = options.column(field.ident.as_ref().unwrap());
+        if !options.generated_by_db() {
+            insert_columns.push(column.clone());
+        }
+        if options.primary_key {
+            primary_key_columns.push(column.clone());
+            primary_key_fields.push(field.clone());
+        }
+        if options.quote || model_options.quote {
+            quoted_columns.push(column.clone());
+        }
+        columns.push(column);
+    }
+
+    // Long aliases are shortened with a hash, which must not collide.
+    let alias_check = quote_spanned! {name.span()=>
+        const _: () = ::usual::base::check_aliases(#prefix, &[#( #columns ),*]);
+    };
+
+    let field_checkers = fields
+        .iter()
+        .zip(columns.iter())
+        .map(|(field, column)| {
+            let checker = field_checker_ident(field.ident.as_ref().unwrap());
+            let column_name = column_name_ident(field.ident.as_ref().unwrap());
+            let vis = &field.vis;
+            let ty = &field.ty;
+            quote! {
+                #[doc(hidden)]
+                #[allow(dead_code, non_snake_case)]
+                #vis fn #checker() -> ::std::marker::PhantomData<#ty> {
+                    ::std::marker::PhantomData
+                }
+
+                #[doc(hidden)]
+                #[allow(dead_code, non_snake_case)]
+                #vis fn #column_name() -> &'static str {
+                    #column
+                }
             }
+        })
+        .collect::<Vec<_>>();
+
+    // Each column is read into a local first, so `compute` functions can borrow it before it's
+    // moved into the model.
+    let field_idents = fields
+        .iter()
+        .map(|field| field.ident.clone().unwrap())
+        .collect::<Vec<_>>();
+    let values = field_idents
+        .iter()
+        .map(|field| Ident::new(&format!("__usual_value_{}", field), Span2::call_site()))
+        .collect::<Vec<_>>();
+    let types = fields.iter().map(|field| &field.ty).collect::<Vec<_>>();
+
+    let mut skipped_initializers = vec![];
+    let mut finishers = vec![];
+    for field in skipped.iter() {
+        let field_name = field.ident.as_ref().unwrap();
+        let options = match UnusualOptions::from_field(field) {
+            Ok(options) => options,
+            Err(e) => return e.to_compile_error().into(),
+        };
+        let initializer = match options.initializer(&field_idents, &values) {
+            Ok(initializer) => initializer,
+            Err(e) => return e.to_compile_error().into(),
+        };
+
+        skipped_initializers.push(quote! { #field_name: #initializer });
+        finishers.extend(options.finisher(field_name));
+    }
+
+    // Fields whose type depends on one of the struct's type parameters need to be readable from a
+    // row for every instantiation the impl covers.
+    let type_params = generics
+        .type_params()
+        .map(|param| param.ident.clone())
+        .collect::<Vec<_>>();
+    let bounded_types = fields
+        .iter()
+        .map(|field| &field.ty)
+        .filter(|ty| mentions_any(ty.to_token_stream(), &type_params))
+        .cloned()
+        .collect::<Vec<_>>();
+    if !bounded_types.is_empty() {
+        let where_clause = generics.make_where_clause();
+        for ty in bounded_types {
+            where_clause
+                .predicates
+                .push(syn::parse_quote! { #ty: ::usual::base::RowValue });
         }
+    }
+    let mut lifetimes = generics.lifetimes().map(|param| param.lifetime.clone());
+    let row_lifetime = lifetimes.next();
+    if let Some(extra) = lifetimes.next() {
+        return syn::Error::new(
+            extra.span(),
+            "A model can only borrow from the row it's read from, so it can have at most one lifetime.",
+        )
+        .to_compile_error()
+        .into();
+    }
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    // `Tracked` keeps a copy of the key that outlives the model, which a model borrowing from the
+    // row can't give.
+    let mut to_row_generics = generics.clone();
+    let primary_key_values = if row_lifetime.is_none() {
+        for ty in primary_key_fields
+            .iter()
+            .map(|field| &field.ty)
+            .filter(|ty| mentions_any(ty.to_token_stream(), &type_params))
+        {
+            to_row_generics
+                .make_where_clause()
+                .predicates
+                .push(syn::parse_quote! {
+                    #ty: ::std::clone::Clone + ::std::marker::Send + ::std::marker::Sync + 'static
+                });
+        }
+        let primary_key_fields = primary_key_fields.iter().map(|field| &field.ident);
 
-            fn from_row_with_prefix(prefix: &str, row: &impl TryGetRow) -> Self {
-              #name {
-                  #(
-                      #fields: row.try_get(format!("{}{}", prefix, stringify!(#fields)).as_str())
-                          .expect(&format!("You messed up while trying to get {} ({}{}) from {}", stringify!(#fields), prefix, stringify!(#fields), stringify!(#name)))
-                  ),*,
-                  #(
-                      #skipped: Default::default()
-                  ),*
-              }
+        quote! {
+            fn primary_key_values(&self) -> Vec<::usual::base::OwnedParam> {
+                vec![#(
+                    Box::new(::std::clone::Clone::clone(&self.#primary_key_fields)) as ::usual::base::OwnedParam
+                ),*]
             }
+        }
+    } else {
+        quote! {}
+    };
+    let (_, _, to_row_where_clause) = to_row_generics.split_for_impl();
+
+    let fields = field_idents;
+
+    let hydrate = quote! {
+        #(
+            let #values: #types = row.try_get(::usual::base::alias(prefix, #columns).as_str())
+                .map_err(|e| ::usual::base::column_error(e, stringify!(#fields), prefix, #columns, stringify!(#name)))?;
+        )*
+
+        #[allow(unused_mut)]
+        let mut model = #name {
+            #( #skipped_initializers, )*
+            #( #fields: #values, )*
+        };
+        #( #finishers )*
+
+        Ok(model)
+    };
+
+    let metadata = quote! {
+        const COLUMNS: &'static [&'static str] = &[#( #columns ),*];
+
+        fn columns_list() -> Vec<&'static str> {
+            vec![#(
+                #columns
+            ),*]
+        }
+
+        fn fields_list() -> Vec<&'static str> {
+            vec![#(
+                stringify!(#fields)
+            ),*]
+        }
+
+        fn insert_columns() -> Vec<&'static str> {
+            vec![#(
+                #insert_columns
+            ),*]
+        }
 
-            fn columns_list() -> Vec<&'static str> {
+        fn primary_key_columns() -> Vec<&'static str> {
+            vec![#(
+                #primary_key_columns
+            ),*]
+        }
+
+        fn quoted_columns() -> Vec<&'static str> {
+            vec![#(
+                #quoted_columns
+            ),*]
+        }
+
+        fn prefix() -> &'static str {
+            #prefix
+        }
+    };
+
+    // A model with a lifetime borrows from the row for that lifetime; any other model can be read
+    // from a row borrowed for any lifetime.
+    let (row_lifetime, ref_generics) = match row_lifetime {
+        Some(lifetime) => (lifetime, generics.clone()),
+        None => {
+            let lifetime = syn::Lifetime::new("'__usual_row", Span2::call_site());
+            let mut ref_generics = generics.clone();
+            ref_generics
+                .params
+                .insert(0, syn::parse_quote! { #lifetime });
+
+            (lifetime, ref_generics)
+        }
+    };
+    let (ref_impl_generics, _, _) = ref_generics.split_for_impl();
+
+    let model_impl = quote! {
+        impl #impl_generics Model for #name #ty_generics #where_clause {
+            #metadata
+        }
+
+        impl #impl_generics ::usual::base::ToRow for #name #ty_generics #to_row_where_clause {
+            fn to_row(&self) -> Vec<(&'static str, ::usual::base::SqlParam<'_>)> {
                 vec![#(
-                    stringify!(#fields)
+                    (#columns, &self.#fields as ::usual::base::SqlParam<'_>)
                 ),*]
             }
 
-            fn prefix() -> &'static str {
-              concat!(stringify!(#name), "__")
+            #primary_key_values
+        }
+
+        impl #ref_impl_generics ::usual::base::ModelRef<#row_lifetime> for #name #ty_generics #where_clause {
+            fn try_from_row_ref_with_prefix<R: TryGetRow>(prefix: &str, row: &#row_lifetime R) -> Result<Self, ::usual::base::Error> {
+                #hydrate
             }
         }
     };
 
+    let track_separator = track_separator();
+    let gen = quote! {
+        #model_impl
+
+        impl #impl_generics #name #ty_generics #where_clause {
+            #( #field_checkers )*
+        }
+
+        #alias_check
+
+        #track_separator
+    };
+
     // proc_macro::Span::call_site()
     //     .note("Thruster code output")
     //     .note(gen.to_string())
@@ -9,12 +9,13 @@ use tokio_postgres::NoTls;
 use tokio_postgres::Row;
 use usual::partial;
 
-use usual::{base::Model, base::TryGetRow, impl_model, query, UsualModel};
+use usual::{base::Model, base::TryGetRow, query, UsualModel};
 
 // Note that Default is required for unusual fields
 #[derive(Clone, Debug, Default)]
 struct NonUsualField;
 
+#[allow(dead_code)]
 #[derive(Debug, UsualModel)]
 struct Post {
     pub id: i64,
@@ -36,22 +37,22 @@ impl TryGetRow for RowWrapper {
             self.0
                 .try_get::<_, i32>(index)
                 .map(|v| *(Box::new(v) as Box<dyn Any>).downcast().unwrap())
-                .map_err(|e| anyhow::Error::from(e))
+                .map_err(anyhow::Error::from)
         } else if t == TypeId::of::<i64>() {
             self.0
                 .try_get::<_, i64>(index)
                 .map(|v| *(Box::new(v) as Box<dyn Any>).downcast().unwrap())
-                .map_err(|e| anyhow::Error::from(e))
+                .map_err(anyhow::Error::from)
         } else if t == TypeId::of::<String>() {
             self.0
                 .try_get::<_, String>(index)
                 .map(|v| *(Box::new(v) as Box<dyn Any>).downcast().unwrap())
-                .map_err(|e| anyhow::Error::from(e))
+                .map_err(anyhow::Error::from)
         } else if t == TypeId::of::<DateTime<Utc>>() {
             self.0
                 .try_get::<_, DateTime<Utc>>(index)
                 .map(|v| *(Box::new(v) as Box<dyn Any>).downcast().unwrap())
-                .map_err(|e| anyhow::Error::from(e))
+                .map_err(anyhow::Error::from)
         } else {
             Err(anyhow::anyhow!(
                 "The type passed in for index {} is unhandled at this time.",
@@ -5,14 +5,19 @@ use std::env;
 use std::error::Error;
 use tokio_postgres::NoTls;
 
-use usual::{base::Model, base::TryGetRow, partial, query, UsualModel};
+use usual::{
+    base::Model, base::ToRow, base::TryGetRow, executor::Executor, partial, query, UsualModel,
+};
 
 #[derive(Debug, UsualModel)]
 struct Post {
+    #[usual(primary_key, generated)]
     pub id: i64,
     pub title: String,
     pub content: String,
+    #[usual(db_default)]
     pub created_at: DateTime<Utc>,
+    #[usual(db_default)]
     pub updated_at: DateTime<Utc>,
 }
 
@@ -31,22 +36,23 @@ async fn main() -> Result<(), Box<dyn Error>> {
         }
     });
 
-    let _ = client
-        .execute(
-            query!("INSERT INTO posts (title, content) VALUES ($1, $2)").as_str(),
-            &[
-                &format!("title {}", Utc::now().timestamp_millis()),
-                &"this is some content",
-            ],
-        )
+    let post = Post {
+        id: 0,
+        title: format!("title {}", Utc::now().timestamp_millis()),
+        content: "this is some content".to_string(),
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+    };
+
+    let inserted = client
+        .fetch_one::<Post>(Post::insert_sql("posts").as_str(), &post.insert_params())
         .await?;
 
+    println!("inserted: {:#?}", inserted);
+
     let rows = client
-        .query(query!("SELECT {Post} FROM posts").as_str(), &[])
-        .await?
-        .iter()
-        .map(Post::from_row)
-        .collect::<Vec<_>>();
+        .fetch_all::<Post>(query!("SELECT {Post} FROM posts").as_str(), &[])
+        .await?;
 
     println!("rows: {:#?}", rows);
 
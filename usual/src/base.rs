@@ -1,25 +1,86 @@
-use anyhow::Error;
+pub use anyhow::Error;
+
+use crate::{
+    dialect::Dialect,
+    write::{insert_sql, primary_key_condition},
+};
+#[cfg(not(feature = "tokio-postgres"))]
+use std::any::Any;
 
 #[cfg(not(feature = "tokio-postgres"))]
 pub trait TryGetRow {
-    fn try_get<'a, T: 'static + Clone>(&'a self, index: &str) -> Result<T, Error>;
+    fn try_get<T: 'static + Clone>(&self, index: &str) -> Result<T, Error>;
 }
 
 #[cfg(feature = "tokio-postgres")]
-use tokio_postgres::{types::FromSql, Row};
+use tokio_postgres::{
+    types::{FromSql, ToSql},
+    Row,
+};
 
+/// With `tokio-postgres`, values are decoded straight from the row's buffer, so they can borrow
+/// from it (`&'a str`, `&'a [u8]`) and don't need to be `Clone`.
 #[cfg(feature = "tokio-postgres")]
 pub trait TryGetRow {
-    fn try_get<'a, T: 'static + Clone + FromSql<'a>>(&'a self, index: &str) -> Result<T, Error>;
+    fn try_get<'a, T: FromSql<'a>>(&'a self, index: &str) -> Result<T, Error>;
 }
 
 #[cfg(feature = "tokio-postgres")]
 impl TryGetRow for Row {
-    fn try_get<'a, T: 'static + Clone + FromSql<'a>>(&'a self, index: &str) -> Result<T, Error> {
-        self.try_get(index).map_err(|e| Error::from(e))
+    fn try_get<'a, T: FromSql<'a>>(&'a self, index: &str) -> Result<T, Error> {
+        self.try_get(index).map_err(Error::from)
+    }
+}
+
+/// A type that can be read out of a row with [`TryGetRow::try_get`]. `#[derive(UsualModel)]` adds
+/// this bound to fields whose type depends on one of the model's type parameters.
+#[cfg(not(feature = "tokio-postgres"))]
+pub trait RowValue: 'static + Clone {}
+
+#[cfg(not(feature = "tokio-postgres"))]
+impl<T: 'static + Clone> RowValue for T {}
+
+/// A type that can be read out of a row with [`TryGetRow::try_get`], and written back with
+/// [`ToRow::to_row`]. `#[derive(UsualModel)]` adds this bound to fields whose type depends on one
+/// of the model's type parameters.
+#[cfg(feature = "tokio-postgres")]
+pub trait RowValue: for<'a> FromSql<'a> + ToSql + Sync {}
+
+#[cfg(feature = "tokio-postgres")]
+impl<T: for<'a> FromSql<'a> + ToSql + Sync> RowValue for T {}
+
+/// A value that can be handed out by a row without a database behind it, which means it has to be
+/// copied out of the row each time it's read.
+#[cfg(not(feature = "tokio-postgres"))]
+pub trait AnyParam: Any {
+    fn to_any(&self) -> Box<dyn Any>;
+}
+
+#[cfg(not(feature = "tokio-postgres"))]
+impl<T: Any + Clone> AnyParam for T {
+    fn to_any(&self) -> Box<dyn Any> {
+        Box::new(self.clone())
     }
 }
 
+/// A single value of a model, as returned by [`ToRow::to_row`].
+#[cfg(not(feature = "tokio-postgres"))]
+pub type SqlParam<'a> = &'a dyn AnyParam;
+
+/// A single value of a model, as returned by [`ToRow::to_row`].
+#[cfg(feature = "tokio-postgres")]
+pub type SqlParam<'a> = &'a (dyn ToSql + Sync);
+
+/// An owned copy of a value of a model, like [`ToRow::primary_key_values`], which can outlive
+/// the model it was copied from.
+#[cfg(not(feature = "tokio-postgres"))]
+pub type OwnedParam = Box<dyn AnyParam + Send + Sync>;
+
+/// An owned copy of a value of a model, like [`ToRow::primary_key_values`], which can outlive
+/// the model it was copied from.
+#[cfg(feature = "tokio-postgres")]
+pub type OwnedParam = Box<dyn ToSql + Send + Sync>;
+
 pub trait IntoEnum<T> {
     fn as_enum(&self) -> T;
     fn as_enum_i32(&self) -> i32;
@@ -27,71 +88,167 @@ pub trait IntoEnum<T> {
 
 #[macro_export]
 macro_rules! impl_model {
-  ($struct:ident {
+  (@model ($($serde:tt)*)
+    $(#[$attr:meta])*
+    $vis:vis struct $struct:ident {
       $(
-          pub $field:ident:$type:ty
-        ),*
+          $(#[$field_attr:meta])*
+          $field_vis:vis $field:ident: $type:ty
+        ),* $(,)?
   }) => {
-      #[derive(Clone, Debug, serde::Deserialize, serde::Serialize)]
-      #[serde(rename_all = "camelCase")]
-      pub struct $struct {
+      #[$crate::usual_serde($($serde)*)]
+      #[derive(Clone, Debug, $crate::UsualModel)]
+      $(#[$attr])*
+      $vis struct $struct {
           $(
-              pub $field: $type,
+              $(#[$field_attr])*
+              $field_vis $field: $type,
           )*
       }
-
-      impl Model for $struct {
-          fn from_row_starting_index(_index: usize, row: &impl TryGetRow) -> Self {
-            $struct {
-                $(
-                    $field: row.try_get(format!("{}{}", Self::prefix(), stringify!($field)).as_str())
-                        .expect(&format!("You messed up while trying to get {} ({}{}) from {}", stringify!($field), Self::prefix(), stringify!($field), stringify!($struct)))
-                ),+
-              }
-          }
-
-          fn from_row_with_prefix(prefix: &str, row: &impl TryGetRow) -> Self {
-            $struct {
-                $(
-                    $field: row.try_get(format!("{}{}", prefix, stringify!($field)).as_str())
-                        .expect(&format!("You messed up while trying to get {} ({}{}) from {}", stringify!($field), prefix, stringify!($field), stringify!($struct)))
-                ),+
-            }
-          }
-
-          fn columns_list() -> Vec<&'static str> {
-              vec![$(
-                  stringify!($field)
-              ),+]
-          }
-
-          fn prefix() -> &'static str {
-            concat!(stringify!($struct), "__")
-          }
-      }
+  };
+  (@model ($($serde:tt)*)
+    $(#[$attr:meta])*
+    $struct:ident $($body:tt)*
+  ) => {
+      $crate::impl_model!(@model ($($serde)*) $(#[$attr])* pub struct $struct $($body)*);
+  };
+  (serde($($serde:tt)*) $($model:tt)*) => {
+      $crate::impl_model!(@model ($($serde)*) $($model)*);
+  };
+  ($($model:tt)*) => {
+      $crate::impl_model!(@model () $($model)*);
   };
 }
 
+/// A table's columns, how they're aliased in a query, and the SQL built from them. Reading the
+/// model out of a row is up to [`ModelRef`]; the methods here that return `Self` are only
+/// available to models that don't borrow from the row.
 pub trait Model
 where
     Self: Sized,
 {
-    fn from_rows<T: TryGetRow>(rows: Vec<T>) -> Vec<Self> {
+    fn from_rows<T: TryGetRow>(rows: Vec<T>) -> Vec<Self>
+    where
+        Self: for<'r> ModelRef<'r>,
+    {
         rows.into_iter().map(|r| Self::from_row(&r)).collect()
     }
 
-    fn from_row_starting_index(index: usize, row: &impl TryGetRow) -> Self;
+    fn from_row_starting_index(_index: usize, row: &impl TryGetRow) -> Self
+    where
+        Self: for<'r> ModelRef<'r>,
+    {
+        Self::from_row(row)
+    }
 
-    fn from_row_with_prefix(_prefix: &str, row: &impl TryGetRow) -> Self {
-        Self::from_row_starting_index(0, row)
+    fn from_row_with_prefix(prefix: &str, row: &impl TryGetRow) -> Self
+    where
+        Self: for<'r> ModelRef<'r>,
+    {
+        Self::from_row_ref_with_prefix(prefix, row)
     }
 
-    fn from_row(row: &impl TryGetRow) -> Self {
-        Self::from_row_starting_index(0, row)
+    fn from_row(row: &impl TryGetRow) -> Self
+    where
+        Self: for<'r> ModelRef<'r>,
+    {
+        Self::from_row_ref(row)
     }
 
+    /// Like [`Model::from_row_with_prefix`], but returns an error instead of panicking when a
+    /// column is missing or has the wrong type.
+    fn try_from_row_with_prefix(prefix: &str, row: &impl TryGetRow) -> Result<Self, Error>
+    where
+        Self: for<'r> ModelRef<'r>,
+    {
+        Self::try_from_row_ref_with_prefix(prefix, row)
+    }
+
+    /// Like [`Model::from_row`], but returns an error instead of panicking when a column is
+    /// missing or has the wrong type.
+    fn try_from_row(row: &impl TryGetRow) -> Result<Self, Error>
+    where
+        Self: for<'r> ModelRef<'r>,
+    {
+        Self::try_from_row_ref(row)
+    }
+
+    /// The same columns as [`Model::columns_list`], as a constant, so `query!` can check the
+    /// aliases a `prefix name` gives them with [`check_aliases`]. Models implemented by hand can
+    /// leave it empty, skipping the check.
+    const COLUMNS: &'static [&'static str] = &[];
+
     fn columns_list() -> Vec<&'static str>;
 
+    /// The names of the model's fields, in the same order as [`Model::columns_list`]. They only
+    /// differ from the columns for fields marked `#[usual(rename = "...")]`.
+    fn fields_list() -> Vec<&'static str> {
+        Self::columns_list()
+    }
+
+    /// The columns of fields marked `#[usual(primary_key)]`.
+    fn primary_key_columns() -> Vec<&'static str> {
+        vec![]
+    }
+
+    /// The columns written as quoted identifiers, so they can be reserved words like `order`
+    /// or keep their case. `#[derive(UsualModel)]` quotes fields marked `#[usual(quote)]`, or every
+    /// column of a struct marked `#[usual(quote)]`.
+    fn quoted_columns() -> Vec<&'static str> {
+        vec![]
+    }
+
+    /// The columns written by [`Model::insert_sql`]. `#[derive(UsualModel)]` leaves out fields marked
+    /// `#[usual(generated)]` or `#[usual(db_default)]`, since the database fills those in.
+    fn insert_columns() -> Vec<&'static str> {
+        Self::columns_list()
+    }
+
+    /// `INSERT INTO table (...) VALUES ($1, ...) RETURNING ...`, returning every column under the
+    /// model's prefix, so the returned row can be read with `from_row`.
+    fn insert_sql(table: &str) -> String {
+        insert_sql(
+            Dialect::Postgres,
+            table,
+            &Self::insert_columns(),
+            &Self::quoted_columns(),
+            1,
+            Some(&Self::columns()),
+        )
+    }
+
+    /// `SELECT ... FROM table WHERE id = $1`, selecting every column under the model's prefix. A
+    /// composite key is matched with `(a, b) = ($1, $2)`.
+    fn find_by_pk_sql(table: &str) -> String {
+        format!(
+            "SELECT {} FROM {} WHERE {}",
+            Self::columns(),
+            table,
+            primary_key_condition(
+                Dialect::Postgres,
+                table,
+                &Self::primary_key_columns(),
+                &Self::quoted_columns(),
+                1
+            )
+        )
+    }
+
+    /// `DELETE FROM table WHERE id = $1`. A composite key is matched with `(a, b) = ($1, $2)`.
+    fn delete_by_pk_sql(table: &str) -> String {
+        format!(
+            "DELETE FROM {} WHERE {}",
+            table,
+            primary_key_condition(
+                Dialect::Postgres,
+                table,
+                &Self::primary_key_columns(),
+                &Self::quoted_columns(),
+                1
+            )
+        )
+    }
+
     fn prefix() -> &'static str {
         ""
     }
@@ -109,17 +266,12 @@ where
         prefix: Option<&str>,
         table: Option<&str>,
     ) -> String {
-        let mut column = match prefix {
-            Some(val) => format!("{} as {}{}", column, val, column),
-            None => column.to_owned(),
-        };
-
-        column = match table {
-            Some(val) => format!("{}.{}", val, column),
-            None => column.to_owned(),
-        };
-
-        column
+        column_with_prefix_and_table(
+            column,
+            prefix,
+            table,
+            Self::quoted_columns().contains(&column),
+        )
     }
 
     fn columns() -> String {
@@ -135,122 +287,1144 @@ where
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use anyhow::{anyhow, Error};
-    use std::{any::Any, collections::HashMap};
-    use usual_proc::{query, UsualModel};
+/// A model that can be written back: its columns paired with its values. `#[derive(UsualModel)]`
+/// implements it next to [`Model`]; models that are only read, like partials, don't need it.
+pub trait ToRow: Model {
+    /// The model's columns and their values, in the same order as [`Model::columns_list`].
+    fn to_row(&self) -> Vec<(&'static str, SqlParam<'_>)>;
 
-    use super::{Model, TryGetRow};
-    use crate::impl_model;
+    /// The model's values, in the same order as [`Model::columns_list`], ready to be passed as the
+    /// parameters of a query.
+    fn params(&self) -> Vec<SqlParam<'_>> {
+        self.to_row().into_iter().map(|(_, value)| value).collect()
+    }
+
+    /// The values for [`Model::insert_sql`], in the same order as [`Model::insert_columns`].
+    fn insert_params(&self) -> Vec<SqlParam<'_>> {
+        let columns = Self::insert_columns();
 
-    struct Row {
-        value: HashMap<String, Box<dyn Any>>,
+        self.to_row()
+            .into_iter()
+            .filter(|(column, _)| columns.contains(column))
+            .map(|(_, value)| value)
+            .collect()
     }
 
-    impl TryGetRow for Row {
-        fn try_get<T: 'static + Clone>(&self, index: &str) -> Result<T, Error> {
-            let value = self.value.get(index).unwrap();
-            let cast: T = (value as &Box<dyn Any>)
-                .downcast_ref::<T>()
-                .ok_or_else(|| {
-                    anyhow!(format!(
-                        "Attempted to get type {}, but was not the correct type",
-                        std::any::type_name::<T>(),
-                    ))
-                })?
-                .clone();
+    /// The values of the primary key, in the same order as [`Model::primary_key_columns`].
+    fn primary_key_params(&self) -> Vec<SqlParam<'_>> {
+        let columns = Self::primary_key_columns();
 
-            Ok(cast)
-        }
+        self.to_row()
+            .into_iter()
+            .filter(|(column, _)| columns.contains(column))
+            .map(|(_, value)| value)
+            .collect()
     }
+    /// Copies of the values of the primary key, in the same order as
+    /// [`Model::primary_key_columns`]. [`Tracked`](crate::write::Tracked) keeps them so an
+    /// [`Update`](crate::write::Update) still finds the row after the key itself is changed.
+    /// `#[derive(UsualModel)]` implements it for models without a lifetime.
+    fn primary_key_values(&self) -> Vec<OwnedParam> {
+        vec![]
+    }
+}
 
-    #[derive(UsualModel)]
-    struct TestModel {
-        pub some_string: String,
-        pub some_int: i32,
+/// Anything that can be read out of a single row: a model, or a tuple of models selected
+/// together, like `(Post, Author)` from `query!("SELECT {Post as p}, {Author as a} ...")`. Each
+/// model in a tuple is read under its own prefix.
+pub trait Hydrate
+where
+    Self: Sized,
+{
+    fn hydrate(row: &impl TryGetRow) -> Result<Self, Error>;
+}
+
+impl<M: for<'r> ModelRef<'r>> Hydrate for M {
+    fn hydrate(row: &impl TryGetRow) -> Result<Self, Error> {
+        M::try_from_row(row)
+    }
+}
+
+macro_rules! impl_hydrate_for_tuple {
+  ($($model:ident),+) => {
+      impl<$($model: for<'r> ModelRef<'r>),+> Hydrate for ($($model,)+) {
+          fn hydrate(row: &impl TryGetRow) -> Result<Self, Error> {
+              Ok(($($model::try_from_row(row)?,)+))
+          }
+      }
+  };
+}
+
+impl_hydrate_for_tuple!(A, B);
+impl_hydrate_for_tuple!(A, B, C);
+impl_hydrate_for_tuple!(A, B, C, D);
+impl_hydrate_for_tuple!(A, B, C, D, E);
+impl_hydrate_for_tuple!(A, B, C, D, E, F);
+
+/// Reads a model out of a row it can borrow from for `'r`, for example
+/// `struct PostView<'r> { title: &'r str }`. `#[derive(UsualModel)]` implements it for every `'r`
+/// on models that own their fields, which is what [`Model::from_row`] and friends need. Borrowing
+/// needs the `tokio-postgres` feature, since that's where [`TryGetRow`] can decode values without
+/// copying them.
+pub trait ModelRef<'r>: Model {
+    /// Reads the model from the columns under `prefix`, or returns an error explaining which
+    /// column is missing or has the wrong type.
+    fn try_from_row_ref_with_prefix<R: TryGetRow>(prefix: &str, row: &'r R) -> Result<Self, Error>;
+
+    /// Like [`ModelRef::try_from_row_ref`], reading the columns under the model's prefix.
+    fn try_from_row_ref<R: TryGetRow>(row: &'r R) -> Result<Self, Error> {
+        Self::try_from_row_ref_with_prefix(Self::prefix(), row)
     }
 
-    #[derive(UsualModel)]
-    struct TestModel2 {
-        pub key: String,
+    /// Like [`ModelRef::try_from_row_ref_with_prefix`], but panics with the error instead of
+    /// returning it.
+    fn from_row_ref_with_prefix<R: TryGetRow>(prefix: &str, row: &'r R) -> Self {
+        Self::try_from_row_ref_with_prefix(prefix, row).unwrap_or_else(|e| panic!("{:#}", e))
     }
 
-    // impl_model!(TestModel {
-    //     pub some_string: String,
-    //     pub some_int: i32
-    // });
+    /// Like [`ModelRef::try_from_row_ref`], but panics with the error instead of returning it.
+    fn from_row_ref<R: TryGetRow>(row: &'r R) -> Self {
+        Self::from_row_ref_with_prefix(Self::prefix(), row)
+    }
+}
+
+/// Explains which column of which model couldn't be read, for the hydration code generated by
+/// `usual-proc`.
+#[doc(hidden)]
+pub fn column_error(error: Error, field: &str, prefix: &str, column: &str, model: &str) -> Error {
+    error.context(format!(
+        "You messed up while trying to get {} ({}{}) from {}",
+        field, prefix, column, model
+    ))
+}
 
-    // impl_model!(TestModel2 {
-    //     pub key: String
-    // });
+/// `count` placeholders numbered from `first`, like `$3, $4`, for `{Model:params}` in `query!`.
+#[doc(hidden)]
+pub fn placeholders(first: usize, count: usize) -> String {
+    (first..first + count)
+        .map(|index| Dialect::Postgres.placeholder(index))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
 
-    #[test]
-    fn it_should_be_able_to_get_from_row() {
-        let some_string = "asdfasdfasdf".to_string();
-        let some_int = 42;
+/// The longest identifier Postgres keeps, in bytes. Longer ones are silently truncated.
+pub const MAX_ALIAS_LEN: usize = 63;
 
-        let mut value: HashMap<String, Box<dyn Any>> = HashMap::new();
-        value.insert("some_string".to_owned(), Box::new(some_string.clone()));
-        value.insert("some_int".to_owned(), Box::new(some_int));
+/// The alias a column is selected and read under, `prefix` followed by `column`. An alias longer
+/// than [`MAX_ALIAS_LEN`] is cut short and ends with `_` and a 32-bit FNV-1a hash of the full
+/// alias instead, so it survives Postgres' limit.
+///
+/// The hash only keeps apart aliases that are cut to the same start, and 32 bits make a
+/// collision unlikely, not impossible: among a few thousand long aliases cut to the same start,
+/// the odds of two colliding are around one in a thousand. [`check_aliases`] makes sure a model's
+/// own aliases don't collide, but columns of different models that happen to share a shortened
+/// alias in one query aren't checked.
+pub fn alias(prefix: &str, column: &str) -> String {
+    let (bytes, len) = alias_bytes(prefix, column);
 
-        let row = Row { value };
+    String::from_utf8(bytes[..len].to_vec()).unwrap()
+}
 
-        let test = TestModel::from_row_with_prefix("", &row);
+/// Fails to compile, when evaluated in a constant, if two of `columns` are read under the same
+/// alias with `prefix`, which can only happen when their shortened aliases collide.
+/// `#[derive(UsualModel)]` checks every model's columns, and `query!` and `partial!` check the
+/// columns of a model given a `prefix name`.
+pub const fn check_aliases(prefix: &str, columns: &[&str]) {
+    let mut i = 0;
+    while i < columns.len() {
+        let (first, first_len) = alias_bytes(prefix, columns[i]);
 
-        assert!(
-            test.some_string == some_string,
-            "It should correctly pull the string value from a row."
-        );
-        assert!(
-            test.some_int == some_int,
-            "It shoudl correctly pull the int value from a row"
-        );
+        let mut j = i + 1;
+        while j < columns.len() {
+            let (second, second_len) = alias_bytes(prefix, columns[j]);
+            if first_len == second_len
+                && same_bytes(&first, &second)
+                && !same_bytes(columns[i].as_bytes(), columns[j].as_bytes())
+            {
+                panic!("Two columns are shortened to the same alias, rename one of them or use a shorter prefix to keep them apart.");
+            }
+
+            j += 1;
+        }
+
+        i += 1;
     }
+}
+
+/// The bytes of [`alias`], and how many of them are used. It's a `const fn` so aliases can be
+/// checked at compile time.
+const fn alias_bytes(prefix: &str, column: &str) -> ([u8; MAX_ALIAS_LEN], usize) {
+    let (prefix, column) = (prefix.as_bytes(), column.as_bytes());
+    let len = prefix.len() + column.len();
+    let mut bytes = [0; MAX_ALIAS_LEN];
 
-    #[test]
-    fn it_should_correctly_insert_columns() {
-        let macro_output = query!("SELECT {TestModel} FROM test_model");
+    // Cut before a character that doesn't fit, leaving room for `_` and the hash.
+    let end = if len <= MAX_ALIAS_LEN {
+        len
+    } else {
+        let mut end = MAX_ALIAS_LEN - 9;
+        while alias_byte(prefix, column, end) & 0xc0 == 0x80 {
+            end -= 1;
+        }
+
+        end
+    };
 
-        assert!(macro_output == "SELECT some_string as TestModel__some_string, some_int as TestModel__some_int FROM test_model")
+    let mut i = 0;
+    while i < end {
+        bytes[i] = alias_byte(prefix, column, i);
+        i += 1;
     }
 
-    #[test]
-    fn it_should_correctly_select_subsets_of_columns() {
-        let macro_output = query!("SELECT {TestModel::some_string} FROM test_model");
+    if end == len {
+        return (bytes, len);
+    }
+
+    // FNV-1a, since the hash has to be the same wherever the alias is built.
+    let mut hash = 0x811c9dc5u32;
+    let mut i = 0;
+    while i < len {
+        hash = (hash ^ alias_byte(prefix, column, i) as u32).wrapping_mul(0x01000193);
+        i += 1;
+    }
+
+    bytes[end] = b'_';
+    let mut digit = 0;
+    while digit < 8 {
+        let nibble = (hash >> (28 - 4 * digit)) & 0xf;
+        bytes[end + 1 + digit] = b"0123456789abcdef"[nibble as usize];
+        digit += 1;
+    }
+
+    (bytes, end + 9)
+}
+
+/// The byte at `index` of `prefix` followed by `column`.
+const fn alias_byte(prefix: &[u8], column: &[u8], index: usize) -> u8 {
+    if index < prefix.len() {
+        prefix[index]
+    } else {
+        column[index - prefix.len()]
+    }
+}
+
+const fn same_bytes(first: &[u8], second: &[u8]) -> bool {
+    if first.len() != second.len() {
+        return false;
+    }
 
-        assert!(macro_output == "SELECT some_string as TestModel__some_string FROM test_model")
+    let mut i = 0;
+    while i < first.len() {
+        if first[i] != second[i] {
+            return false;
+        }
+        i += 1;
     }
 
-    #[test]
-    fn it_should_correctly_select_subsets_of_multiple_columns() {
-        let macro_output = query!("SELECT {TestModel::some_string,some_int} FROM test_model");
+    true
+}
+
+fn column_with_prefix_and_table(
+    column: &str,
+    prefix: Option<&str>,
+    table: Option<&str>,
+    quote: bool,
+) -> String {
+    let quoted = |identifier: String| match quote {
+        true => Dialect::Postgres.quote(&identifier),
+        false => identifier,
+    };
+
+    let mut column = match prefix {
+        Some(val) => format!(
+            "{} as {}",
+            quoted(column.to_owned()),
+            quoted(alias(val, column))
+        ),
+        None => quoted(column.to_owned()),
+    };
+
+    column = match table {
+        Some(val) => format!("{}.{}", val, column),
+        None => column.to_owned(),
+    };
+
+    column
+}
+
+#[cfg(test)]
+mod tests {
+    // The same cases run against models from `#[derive(UsualModel)]` and from `impl_model!`.
+    macro_rules! model_tests {
+        () => {
+            use usual_proc::{partial, query};
+
+            use crate::{
+                base::{Model, TryGetRow},
+                testing::MockRow,
+            };
+
+            #[test]
+            fn it_should_be_able_to_get_from_row() {
+                let some_string = "asdfasdfasdf".to_string();
+                let some_int = 42;
+
+                let row = MockRow::new()
+                    .set("some_string", some_string.clone())
+                    .set("some_int", some_int);
+
+                let test = TestModel::from_row_with_prefix("", &row);
+
+                assert!(
+                    test.some_string == some_string,
+                    "It should correctly pull the string value from a row."
+                );
+                assert!(
+                    test.some_int == some_int,
+                    "It shoudl correctly pull the int value from a row"
+                );
+            }
+
+            #[test]
+            fn it_should_correctly_insert_columns() {
+                let macro_output = query!("SELECT {TestModel} FROM test_model");
+
+                assert!(macro_output == "SELECT some_string as TestModel__some_string, some_int as TestModel__some_int FROM test_model")
+            }
+
+            #[test]
+            fn it_should_correctly_select_subsets_of_columns() {
+                let macro_output = query!("SELECT {TestModel::some_string} FROM test_model");
+
+                assert!(macro_output == "SELECT some_string as TestModel__some_string FROM test_model")
+            }
+
+            #[test]
+            fn it_should_correctly_select_subsets_of_multiple_columns() {
+                let macro_output = query!("SELECT {TestModel::some_string,some_int} FROM test_model");
+
+                assert!(macro_output == "SELECT some_string as TestModel__some_string, some_int as TestModel__some_int FROM test_model")
+            }
+
+            #[test]
+            fn it_should_select_every_column_but_excluded_ones() {
+                let macro_output = query!("SELECT {TestModel::!some_int as t} FROM test_model as t");
+
+                assert!(
+                    macro_output == "SELECT t.some_string as TestModel__some_string FROM test_model as t",
+                    "It should leave the excluded columns out."
+                )
+            }
+
+            #[test]
+            fn it_should_insert_bare_columns_and_params() {
+                let macro_output = query!("INSERT INTO test_model ({TestModel:cols}) VALUES ({TestModel:params})");
+
+                assert!(
+                    macro_output == "INSERT INTO test_model (some_string, some_int) VALUES ($1, $2)",
+                    "It should list the columns without aliases, and a placeholder for each."
+                );
+
+                let macro_output = query!("INSERT INTO test_model (id, {TestModel:cols}) VALUES ($1, {TestModel:params}), ($2, {TestModel:params})");
+
+                assert!(
+                    macro_output == "INSERT INTO test_model (id, some_string, some_int) VALUES ($1, $3, $4), ($2, $5, $6)",
+                    "It should number placeholders after the query's own parameters and each other."
+                );
+            }
+
+            #[test]
+            fn it_should_correctly_insert_columns_with_a_table() {
+                let macro_output = query!("SELECT {TestModel as t} FROM test_model as t");
+
+                assert!(macro_output == "SELECT t.some_string as TestModel__some_string, t.some_int as TestModel__some_int FROM test_model as t")
+            }
+
+            #[test]
+            fn it_should_correctly_insert_columns_with_multiple_tables() {
+                let macro_output = query!("SELECT {TestModel as t}, {TestModel2 as t2} FROM test_model as t JOIN test_model as t2 on t.id = t2.id");
+
+                println!("macro_output: {}", macro_output);
+
+                assert!(macro_output == "SELECT t.some_string as TestModel__some_string, t.some_int as TestModel__some_int, t2.key as TestModel2__key FROM test_model as t JOIN test_model as t2 on t.id = t2.id")
+            }
+
+            #[test]
+            fn it_should_correctly_select_subsets_of_columns_with_a_table() {
+                let macro_output = query!("SELECT {TestModel::some_string as t} FROM test_model as t");
+
+                assert!(
+                    macro_output == "SELECT t.some_string as TestModel__some_string FROM test_model as t"
+                )
+            }
+
+            #[test]
+            fn it_should_return_errors_for_missing_columns() {
+                let row = MockRow::new().set("TestModel__some_string", "asdf".to_string());
+
+                let error = TestModel::try_from_row(&row).err().unwrap();
+
+                assert!(
+                    format!("{:#}", error).contains("some_int (TestModel__some_int) from TestModel"),
+                    "It should say which column couldn't be read."
+                );
+            }
+
+            #[test]
+            fn it_should_get_partials_from_row() {
+                let some_string = "asdfasdfasdf".to_string();
+
+                let row = MockRow::new()
+                    .set("TestModel__some_string", some_string.clone());
+
+                let test = (partial!(TestModel, some_string as String))(&row);
+
+                assert!(
+                    test.some_string == some_string,
+                    "It should correctly pull the partial's string value from a row."
+                );
+            }
+
+            #[test]
+            fn it_should_correctly_select_columns_with_a_prefix() {
+                let macro_output = query!("SELECT {TestModel as t prefix other} FROM test_model as t");
+
+                assert!(macro_output == "SELECT t.some_string as other__some_string, t.some_int as other__some_int FROM test_model as t")
+            }
+
+            #[test]
+            fn it_should_correctly_select_subsets_of_columns_from_a_self_join() {
+                let macro_output = query!("SELECT {TestModel::some_string as t}, {TestModel::some_string as p prefix parent} FROM test_model as t JOIN test_model as p on t.parent_id = p.id");
+
+                assert!(macro_output == "SELECT t.some_string as TestModel__some_string, p.some_string as parent__some_string FROM test_model as t JOIN test_model as p on t.parent_id = p.id")
+            }
+
+            #[test]
+            fn it_should_get_prefixed_partials_from_row() {
+                let row = MockRow::new()
+                    .set("TestModel__some_string", "child".to_string())
+                    .set("parent__some_string", "parent".to_string());
 
-        assert!(macro_output == "SELECT some_string as TestModel__some_string, some_int as TestModel__some_int FROM test_model")
+                let child = (partial!(TestModel, some_string as String))(&row);
+                let parent = (partial!(TestModel prefix parent, some_string as String))(&row);
+
+                assert!(
+                    child.some_string == "child",
+                    "It should pull the unprefixed partial from the model's columns."
+                );
+                assert!(
+                    parent.some_string == "parent",
+                    "It should pull the prefixed partial from the prefixed columns."
+                );
+            }
+
+            #[cfg(feature = "serde")]
+            #[test]
+            fn it_should_serialize_partials_as_camel_case_by_default() {
+                let row = MockRow::new()
+                    .set("TestModel__some_string", "asdf".to_string());
+
+                let camel = (partial!(TestModel, some_string as String))(&row);
+                let snake =
+                    (partial!(serde(rename_all = "snake_case") TestModel, some_string as String))(&row);
+
+                assert!(
+                    serde_json::to_string(&camel).unwrap() == r#"{"someString":"asdf"}"#,
+                    "It should rename partial fields to camelCase by default."
+                );
+                assert!(
+                    serde_json::to_string(&snake).unwrap() == r#"{"some_string":"asdf"}"#,
+                    "It should use the requested rename strategy."
+                );
+                assert!(
+                    (partial!(serde(skip) TestModel, some_string as String))(&row).some_string == "asdf",
+                    "It should still read partials without the serde derives."
+                );
+            }
+        };
     }
 
-    #[test]
-    fn it_should_correctly_insert_columns_with_a_table() {
-        let macro_output = query!("SELECT {TestModel as t} FROM test_model as t");
+    mod derive_form {
+        use usual_proc::UsualModel;
+
+        use crate::base::{alias, Hydrate, ModelRef, ToRow, MAX_ALIAS_LEN};
+
+        #[derive(UsualModel)]
+        struct TestModel {
+            pub some_string: String,
+            pub some_int: i32,
+        }
+
+        #[allow(dead_code)]
+        #[derive(UsualModel)]
+        struct TestModel2 {
+            pub key: String,
+        }
+
+        struct NotDefault(&'static str);
+
+        fn not_default() -> NotDefault {
+            NotDefault("from a function")
+        }
+
+        struct Shouted(String);
+
+        fn shout(key: &str, times: &i32) -> Shouted {
+            Shouted(key.to_uppercase().repeat(*times as usize))
+        }
+
+        #[derive(UsualModel)]
+        struct ComputedModel {
+            pub key: String,
+            pub times: i32,
+            #[unusual(default = "not_default")]
+            pub handle: NotDefault,
+            #[unusual(compute = "shout(key, times)")]
+            pub shouted: Shouted,
+            #[unusual(compute = "ComputedModel::summary")]
+            pub summary: String,
+            #[unusual(default = "not_default", compute = "ComputedModel::handle_for")]
+            pub keyed_handle: NotDefault,
+        }
+
+        impl ComputedModel {
+            fn summary(&self) -> String {
+                format!("{} x{} ({})", self.key, self.times, self.shouted.0)
+            }
+
+            fn handle_for(&self) -> NotDefault {
+                NotDefault(if self.times > 0 { "for the model" } else { "" })
+            }
+        }
+
+        #[derive(UsualModel)]
+        struct Record<Id, P: Clone> {
+            pub id: Id,
+            pub payload: P,
+            pub note: String,
+        }
+
+        #[derive(UsualModel)]
+        struct Tagged<'r> {
+            pub key: String,
+            #[unusual]
+            pub tag: Option<&'r str>,
+        }
+
+        #[cfg(feature = "tokio-postgres")]
+        #[derive(UsualModel)]
+        struct PostView<'r> {
+            pub id: i64,
+            pub title: &'r str,
+        }
+
+        #[derive(UsualModel)]
+        struct RenamedModel {
+            pub id: i64,
+            #[usual(rename = "post_title")]
+            pub title: String,
+            #[unusual]
+            pub cached: Option<String>,
+        }
+
+        #[derive(UsualModel)]
+        struct InsertedModel {
+            #[usual(primary_key, generated)]
+            pub id: i64,
+            #[usual(rename = "post_title")]
+            pub title: String,
+            #[usual(db_default)]
+            pub created_at: i64,
+        }
+
+        #[derive(UsualModel)]
+        struct Membership {
+            #[usual(primary_key)]
+            pub user_id: i64,
+            #[usual(primary_key)]
+            pub group_id: i64,
+            pub role: String,
+        }
+
+        #[derive(UsualModel)]
+        struct Order {
+            #[usual(primary_key, generated, quote)]
+            pub id: i64,
+            #[usual(quote)]
+            pub order: i64,
+            #[usual(quote, rename = "createdAt")]
+            pub created_at: i64,
+            pub total: i64,
+        }
+
+        #[derive(UsualModel)]
+        #[usual(quote)]
+        struct QuotedModel {
+            pub user: String,
+        }
+
+        #[derive(UsualModel)]
+        #[usual(prefix = "p")]
+        struct PrefixedModel {
+            pub id: i64,
+            pub title: String,
+        }
+
+        #[derive(UsualModel)]
+        struct ModelWithAnUnusuallyLongNameForItsAliases {
+            pub short: i64,
+            pub a_field_name_long_enough_to_overflow_the_limit: i64,
+            pub a_field_name_long_enough_to_overflow_the_limit_too: i64,
+        }
+
+        model_tests!();
+
+        #[test]
+        fn it_should_hydrate_tuples_of_models() {
+            let row = MockRow::new()
+                .set("TestModel__some_string", "asdf".to_string())
+                .set("TestModel__some_int", 42)
+                .set("TestModel2__key", "key".to_string());
+
+            let (test, test2) = <(TestModel, TestModel2)>::hydrate(&row).unwrap();
+
+            assert!(
+                test.some_int == 42 && test2.key == "key",
+                "It should read each model of a tuple from the same row."
+            );
+            assert!(
+                <(TestModel, InsertedModel)>::hydrate(&row).is_err(),
+                "It should fail if any model of the tuple can't be read."
+            );
+        }
+
+        #[test]
+        fn it_should_get_models_with_a_lifetime_from_row() {
+            let row = MockRow::new().set("Tagged__key", "asdf".to_string());
+
+            let test = Tagged::from_row_ref(&row);
+
+            assert!(
+                test.key == "asdf" && test.tag.is_none(),
+                "It should pull models with a lifetime from a row."
+            );
+            assert!(
+                query!("SELECT {Tagged} FROM tags") == "SELECT key as Tagged__key FROM tags",
+                "It should select the columns of models with a lifetime."
+            );
+        }
+
+        #[cfg(feature = "tokio-postgres")]
+        #[test]
+        fn it_should_borrow_fields_from_row() {
+            let row = MockRow::new()
+                .set("PostView__id", 1_i64)
+                .set("PostView__title", "A title");
+
+            let view = PostView::from_row_ref(&row);
+            let title: &str = view.title;
+
+            assert!(
+                view.id == 1 && title == "A title",
+                "It should borrow fields of models with a lifetime from the row."
+            );
+            assert!(
+                PostView::columns() == "id as PostView__id, title as PostView__title",
+                "It should describe the columns of models with a lifetime through Model."
+            );
+        }
+
+        #[test]
+        fn it_should_get_generic_models_from_row() {
+            let row = MockRow::new()
+                .set("Record__id", 42_i64)
+                .set("Record__payload", vec![1_u8, 2, 3])
+                .set("Record__note", "asdf".to_string());
 
-        assert!(macro_output == "SELECT t.some_string as TestModel__some_string, t.some_int as TestModel__some_int FROM test_model as t")
+            let test = Record::<i64, Vec<u8>>::from_row(&row);
+            let partial = (partial!(Record<i64, Vec<u8>>, id as i64))(&row);
+
+            assert!(
+                test.id == 42 && test.payload == vec![1, 2, 3] && test.note == "asdf",
+                "It should pull generic fields from a row."
+            );
+            assert!(
+                partial.id == 42,
+                "It should pull partials of generic models from a row."
+            );
+            assert!(
+                query!("SELECT {Record<i64,String>} FROM records")
+                    == "SELECT id as Record__id, payload as Record__payload, note as Record__note FROM records",
+                "It should select the columns of generic models."
+            );
+            assert!(
+                query!("SELECT {Record<i64, Vec<u8>>::id as r} FROM records as r")
+                    == "SELECT r.id as Record__id FROM records as r",
+                "It should allow spaces between generic arguments."
+            );
+        }
+
+        #[test]
+        fn it_should_fill_unusual_fields_from_functions() {
+            let row = MockRow::new()
+                .set("ComputedModel__key", "asdf".to_string())
+                .set("ComputedModel__times", 2);
+
+            let test = ComputedModel::from_row(&row);
+
+            assert!(
+                test.handle.0 == "from a function",
+                "It should initialize unusual fields from their default function."
+            );
+            assert!(
+                test.shouted.0 == "ASDFASDF" && test.key == "asdf",
+                "It should compute unusual fields from the hydrated columns, without needing a default."
+            );
+            assert!(
+                test.summary == "asdf x2 (ASDFASDF)",
+                "It should compute unusual fields from the rest of the model once it's built."
+            );
+            assert!(
+                test.keyed_handle.0 == "for the model",
+                "It should start a field computed from the model with its default function."
+            );
+        }
+
+        #[test]
+        fn it_should_turn_models_into_rows() {
+            let model = RenamedModel {
+                id: 42,
+                title: "asdf".to_string(),
+                cached: Some("cached".to_string()),
+            };
+
+            let columns = model
+                .to_row()
+                .into_iter()
+                .map(|(column, _)| column)
+                .collect::<Vec<_>>();
+
+            assert!(
+                columns == vec!["id", "post_title"] && model.params().len() == 2,
+                "It should skip unusual fields and use renamed columns."
+            );
+
+            let test = RenamedModel::from_row(&MockRow::from_model(&model));
+
+            assert!(
+                test.id == 42 && test.title == "asdf" && test.cached.is_none(),
+                "It should read back the row a model was turned into."
+            );
+        }
+
+        #[test]
+        fn it_should_respect_renamed_columns() {
+            let row = MockRow::new().set("RenamedModel__post_title", "asdf".to_string());
+
+            let partial = (partial!(RenamedModel, title as String))(&row);
+
+            assert!(
+                partial.title == "asdf",
+                "It should pull partials from renamed columns."
+            );
+            assert!(
+                query!("SELECT {RenamedModel::id,title as p} FROM posts as p")
+                    == "SELECT p.id as RenamedModel__id, p.post_title as RenamedModel__post_title FROM posts as p",
+                "It should select renamed columns."
+            );
+            assert!(
+                query!("SELECT {RenamedModel::!title} FROM posts")
+                    == "SELECT id as RenamedModel__id FROM posts",
+                "It should exclude renamed columns by their field name."
+            );
+        }
+
+        #[test]
+        fn it_should_replace_named_params() {
+            let author = 1i64;
+            let since = "2021-01-01".to_string();
+
+            let statement = query!(
+                "SELECT {TestModel} FROM test_model WHERE (author = :author OR editor = :author) AND created_at::date > :since AND note != ':author'",
+                author = &author,
+                since = &since,
+            );
+
+            assert!(
+                statement.sql
+                    == "SELECT some_string as TestModel__some_string, some_int as TestModel__some_int FROM test_model WHERE (author = $1 OR editor = $1) AND created_at::date > $2 AND note != ':author'",
+                "It should number each name once, skipping casts and quoted text."
+            );
+            assert!(
+                statement.params.len() == 2,
+                "It should return a param for each name, in order."
+            );
+
+            let statement = query!(
+                "SELECT tags[1:limit] FROM test_model WHERE tags[:limit:2] != '{{}}' AND author = :author",
+                author = &author,
+            );
+
+            assert!(
+                statement.sql
+                    == "SELECT tags[1:limit] FROM test_model WHERE tags[:limit:2] != '{}' AND author = $1",
+                "It should skip array slices."
+            );
+
+            let statement = query!(
+                "SELECT id FROM test_model -- it's :not a param\n WHERE a = :author /* :nor 'this */ AND b != $$ :or $1 $$ AND c != $tag$ it's :tagged $tag$",
+                author = &author,
+            );
+
+            assert!(
+                statement.sql
+                    == "SELECT id FROM test_model -- it's :not a param\n WHERE a = $1 /* :nor 'this */ AND b != $$ :or $1 $$ AND c != $tag$ it's :tagged $tag$",
+                "It should skip comments and dollar-quoted strings."
+            );
+            assert!(
+                query!("SELECT tags[1:n], :psql_variable FROM test_model")
+                    == "SELECT tags[1:n], :psql_variable FROM test_model",
+                "It should leave the query alone without named arguments."
+            );
+            assert!(
+                query!("SELECT '{{TestModel}}', {TestModel::some_int} FROM test_model")
+                    == "SELECT '{TestModel}', some_int as TestModel__some_int FROM test_model",
+                "It should leave escaped braces alone."
+            );
+        }
+
+        #[test]
+        fn it_should_shorten_long_aliases() {
+            use ModelWithAnUnusuallyLongNameForItsAliases as LongModel;
+
+            let short = alias(LongModel::prefix(), "short");
+            let first = alias(
+                LongModel::prefix(),
+                "a_field_name_long_enough_to_overflow_the_limit",
+            );
+            let second = alias(
+                LongModel::prefix(),
+                "a_field_name_long_enough_to_overflow_the_limit_too",
+            );
+
+            assert!(
+                short == "ModelWithAnUnusuallyLongNameForItsAliases__short",
+                "It should leave aliases under the limit alone."
+            );
+            assert!(
+                first.len() == MAX_ALIAS_LEN
+                    && second.len() == MAX_ALIAS_LEN
+                    && first != second
+                    && first.starts_with("ModelWithAnUnusuallyLongNameForItsAliases__a_field"),
+                "It should cut long aliases to the limit, keeping them apart with a hash."
+            );
+            assert!(
+                LongModel::columns().ends_with(&format!(
+                    "a_field_name_long_enough_to_overflow_the_limit_too as {}",
+                    second
+                )),
+                "It should select columns under the short alias."
+            );
+            assert!(
+                query!("SELECT {TestModel prefix a_prefix_long_enough_for_every_alias_to_be_shortened} FROM test_model")
+                    == format!(
+                        "SELECT some_string as {}, some_int as {} FROM test_model",
+                        alias("a_prefix_long_enough_for_every_alias_to_be_shortened__", "some_string"),
+                        alias("a_prefix_long_enough_for_every_alias_to_be_shortened__", "some_int")
+                    ),
+                "It should shorten aliases under a long prefix in queries."
+            );
+
+            let model = LongModel {
+                short: 1,
+                a_field_name_long_enough_to_overflow_the_limit: 2,
+                a_field_name_long_enough_to_overflow_the_limit_too: 3,
+            };
+            let row = MockRow::new()
+                .set(&short, 1i64)
+                .set(&first, 2i64)
+                .set(&second, 3i64);
+
+            assert!(
+                LongModel::from_row(&row).a_field_name_long_enough_to_overflow_the_limit_too
+                    == model.a_field_name_long_enough_to_overflow_the_limit_too,
+                "It should read columns back from the short alias."
+            );
+        }
+
+        #[test]
+        fn it_should_use_a_custom_prefix() {
+            let row = MockRow::from_model(&PrefixedModel {
+                id: 1,
+                title: "asdf".to_string(),
+            });
+
+            assert!(
+                query!("SELECT {PrefixedModel} FROM posts")
+                    == "SELECT id as p__id, title as p__title FROM posts",
+                "It should select columns under the custom prefix, followed by the separator."
+            );
+            assert!(
+                PrefixedModel::from_row(&row).title == "asdf",
+                "It should read the model back from the custom prefix."
+            );
+            assert!(
+                (partial!(PrefixedModel, title as String))(&row).title == "asdf",
+                "It should read partials from the custom prefix."
+            );
+            assert!(
+                query!("SELECT {PrefixedModel as p prefix parent} FROM posts as p")
+                    == "SELECT p.id as parent__id, p.title as parent__title FROM posts as p",
+                "It should still put the separator after a prefix named in the query."
+            );
+        }
+
+        #[test]
+        fn it_should_quote_identifiers() {
+            assert!(
+                query!("SELECT {Order as o} FROM orders as o")
+                    == r#"SELECT o."id" as "Order__id", o."order" as "Order__order", o."createdAt" as "Order__createdAt", o.total as Order__total FROM orders as o"#,
+                "It should quote the columns and aliases of quoted fields."
+            );
+            assert!(
+                Order::insert_sql("orders")
+                    == r#"INSERT INTO orders ("order", "createdAt", total) VALUES ($1, $2, $3) RETURNING "id" as "Order__id", "order" as "Order__order", "createdAt" as "Order__createdAt", total as Order__total"#,
+                "It should quote inserted columns."
+            );
+            assert!(
+                Order::find_by_pk_sql("orders").ends_with(r#"WHERE "id" = $1"#),
+                "It should quote primary keys."
+            );
+            assert!(
+                query!("INSERT INTO orders ({Order::!id:cols})")
+                    == r#"INSERT INTO orders ("order", "createdAt", total)"#,
+                "It should quote bare columns."
+            );
+            assert!(
+                QuotedModel::columns() == r#""user" as "QuotedModel__user""#,
+                "It should quote every column of a quoted model."
+            );
+
+            let row = MockRow::new()
+                .set("Order__id", 1i64)
+                .set("Order__order", 2i64)
+                .set("Order__createdAt", 3i64)
+                .set("Order__total", 4i64);
+
+            assert!(
+                Order::from_row(&row).created_at == 3,
+                "It should read the quoted alias with its case intact."
+            );
+        }
+
+        #[test]
+        fn it_should_generate_inserts() {
+            let model = InsertedModel {
+                id: 0,
+                title: "asdf".to_string(),
+                created_at: 0,
+            };
+
+            assert!(
+                InsertedModel::insert_sql("posts")
+                    == "INSERT INTO posts (post_title) VALUES ($1) RETURNING id as InsertedModel__id, post_title as InsertedModel__post_title, created_at as InsertedModel__created_at",
+                "It should leave generated keys and database defaults out of inserts."
+            );
+            assert!(
+                model.insert_params().len() == 1,
+                "It should only pass the values of the inserted columns."
+            );
+            assert!(
+                TestModel::insert_sql("test_model")
+                    == "INSERT INTO test_model (some_string, some_int) VALUES ($1, $2) RETURNING some_string as TestModel__some_string, some_int as TestModel__some_int",
+                "It should insert every column by default."
+            );
+
+            let membership = Membership {
+                user_id: 1,
+                group_id: 2,
+                role: "admin".to_string(),
+            };
+
+            assert!(
+                Membership::insert_sql("memberships")
+                    == "INSERT INTO memberships (user_id, group_id, role) VALUES ($1, $2, $3) RETURNING user_id as Membership__user_id, group_id as Membership__group_id, role as Membership__role",
+                "It should insert primary keys that aren't generated, like composite keys."
+            );
+            assert!(
+                membership.insert_params().len() == 3,
+                "It should pass the values of the primary key to inserts."
+            );
+        }
+
+        #[test]
+        fn it_should_find_and_delete_by_primary_key() {
+            assert!(
+                InsertedModel::primary_key_columns() == vec!["id"]
+                    && InsertedModel::find_by_pk_sql("posts")
+                        == "SELECT id as InsertedModel__id, post_title as InsertedModel__post_title, created_at as InsertedModel__created_at FROM posts WHERE id = $1"
+                    && InsertedModel::delete_by_pk_sql("posts") == "DELETE FROM posts WHERE id = $1",
+                "It should find and delete rows by their primary key."
+            );
+
+            let membership = Membership {
+                user_id: 1,
+                group_id: 2,
+                role: "admin".to_string(),
+            };
+
+            assert!(
+                Membership::primary_key_columns() == vec!["user_id", "group_id"]
+                    && Membership::delete_by_pk_sql("memberships")
+                        == "DELETE FROM memberships WHERE (user_id, group_id) = ($1, $2)",
+                "It should match composite primary keys as a whole."
+            );
+            assert!(
+                membership.primary_key_params().len() == 2,
+                "It should pass every column of the primary key."
+            );
+        }
     }
 
-    #[test]
-    fn it_should_correctly_insert_columns_with_multiple_tables() {
-        let macro_output = query!("SELECT {TestModel as t}, {TestModel2 as t2} FROM test_model as t JOIN test_model as t2 on t.id = t2.id");
+    mod impl_model_form {
+        impl_model!(TestModel {
+            pub some_string: String,
+            pub some_int: i32
+        });
+
+        impl_model!(
+            #[allow(dead_code)]
+            TestModel2 {
+                pub key: String
+            }
+        );
+
+        impl_model!(
+            /// A model using everything `impl_model!` supports.
+            #[derive(Default)]
+            struct FullModel {
+                /// A column.
+                pub key: String,
+                private_int: i32,
+                #[unusual]
+                non_sql: Option<String>,
+            }
+        );
+
+        impl_model!(
+            serde(rename_all = "snake_case")
+            #[allow(dead_code)]
+            SnakeCaseModel {
+                pub some_string: String
+            }
+        );
+
+        // Skipping the derives leaves the model free to derive serde itself.
+        impl_model!(
+            serde(skip)
+            #[allow(dead_code)]
+            #[cfg_attr(feature = "serde", derive(serde::Serialize))]
+            UnrenamedModel {
+                pub some_string: String
+            }
+        );
+
+        model_tests!();
 
-        println!("macro_output: {}", macro_output);
+        #[cfg(feature = "serde")]
+        #[test]
+        fn it_should_apply_serde_options_to_models() {
+            let default = TestModel {
+                some_string: "asdf".to_string(),
+                some_int: 1,
+            };
+            let snake = SnakeCaseModel {
+                some_string: "asdf".to_string(),
+            };
+            let skipped = UnrenamedModel {
+                some_string: "asdf".to_string(),
+            };
 
-        assert!(macro_output == "SELECT t.some_string as TestModel__some_string, t.some_int as TestModel__some_int, t2.key as TestModel2__key FROM test_model as t JOIN test_model as t2 on t.id = t2.id")
+            assert!(
+                serde_json::to_string(&default).unwrap() == r#"{"someString":"asdf","someInt":1}"#,
+                "It should rename model fields to camelCase by default."
+            );
+            assert!(
+                serde_json::to_string(&snake).unwrap() == r#"{"some_string":"asdf"}"#,
+                "It should use the requested rename strategy."
+            );
+            assert!(
+                serde_json::to_string(&skipped).unwrap() == r#"{"some_string":"asdf"}"#,
+                "It should leave the serde derives out when skipped."
+            );
+        }
+
+        #[test]
+        fn it_should_support_private_and_unusual_fields() {
+            let row = MockRow::new()
+                .set("FullModel__key", "asdf".to_string())
+                .set("FullModel__private_int", 42);
+
+            let test = FullModel::from_row(&row);
+
+            assert!(
+                test.key == "asdf" && test.private_int == 42,
+                "It should pull private fields from a row."
+            );
+            assert!(test.non_sql.is_none(), "It should default unusual fields.");
+            assert!(
+                FullModel::columns_list() == vec!["key", "private_int"],
+                "It should leave unusual fields out of the columns."
+            );
+        }
     }
 
-    #[test]
-    fn it_should_correctly_select_subsets_of_columns_with_a_table() {
-        let macro_output = query!("SELECT {TestModel::some_string as t} FROM test_model as t");
+    mod hand_written_form {
+        use crate::{
+            base::{column_error, Error, Model, ModelRef, TryGetRow},
+            testing::MockRow,
+        };
 
-        assert!(
-            macro_output == "SELECT t.some_string as TestModel__some_string FROM test_model as t"
-        )
+        // Only read, so it doesn't implement `ToRow`.
+        struct Counter {
+            pub count: i64,
+        }
+
+        impl Model for Counter {
+            fn columns_list() -> Vec<&'static str> {
+                vec!["count"]
+            }
+
+            fn prefix() -> &'static str {
+                "Counter__"
+            }
+        }
+
+        impl<'r> ModelRef<'r> for Counter {
+            fn try_from_row_ref_with_prefix<R: TryGetRow>(
+                prefix: &str,
+                row: &'r R,
+            ) -> Result<Self, Error> {
+                Ok(Counter {
+                    count: row
+                        .try_get(&format!("{}count", prefix))
+                        .map_err(|e| column_error(e, "count", prefix, "count", "Counter"))?,
+                })
+            }
+        }
+
+        #[test]
+        fn it_should_read_hand_written_models() {
+            let row = MockRow::new().set("Counter__count", 3_i64);
+
+            assert!(
+                Counter::from_row(&row).count == 3,
+                "It should read models implemented by hand, without writing them back."
+            );
+            assert!(
+                Counter::columns() == "count as Counter__count",
+                "It should select the columns of models implemented by hand."
+            );
+        }
     }
 }
@@ -0,0 +1,421 @@
+//! Bulk loading and exporting models with `COPY ... BINARY`:
+//!
+//! ```ignore
+//! use usual::copy::CopyModels;
+//!
+//! let written = client.copy_in_models("posts", &posts).await?;
+//! let posts = client.copy_out_models::<Post>("posts").await?;
+//! ```
+//!
+//! Every column of the model is copied both ways, generated keys included, so rows copied out of
+//! one table can be copied into another as they are. Each value is encoded and decoded here, in
+//! Postgres' binary format for its column's type, while `tokio_postgres::binary_copy` frames the
+//! rows.
+use std::{
+    any::type_name,
+    error::Error as StdError,
+    future::{poll_fn, Future},
+    pin::pin,
+};
+
+use anyhow::anyhow;
+use bytes::BytesMut;
+use futures_core::Stream;
+use tokio_postgres::{
+    binary_copy::{BinaryCopyInWriter, BinaryCopyOutRow, BinaryCopyOutStream},
+    types::{to_sql_checked, FromSql, IsNull, ToSql, Type},
+    Client, Transaction,
+};
+
+use crate::{
+    base::{alias, Error, Model, ModelRef, ToRow, TryGetRow},
+    dialect::Dialect,
+    write::column_sql,
+};
+
+/// A value in Postgres' binary format, or `None` for `NULL`, passed to and from
+/// `tokio_postgres::binary_copy` as is.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RawValue(pub Option<Vec<u8>>);
+
+impl ToSql for RawValue {
+    fn to_sql(
+        &self,
+        _ty: &Type,
+        out: &mut BytesMut,
+    ) -> Result<IsNull, Box<dyn StdError + Sync + Send>> {
+        match &self.0 {
+            Some(value) => {
+                out.extend_from_slice(value);
+                Ok(IsNull::No)
+            }
+            None => Ok(IsNull::Yes),
+        }
+    }
+
+    // The value was encoded for its column's type by `encode_row`.
+    fn accepts(_ty: &Type) -> bool {
+        true
+    }
+
+    to_sql_checked!();
+}
+
+impl<'a> FromSql<'a> for RawValue {
+    fn from_sql(_ty: &Type, raw: &'a [u8]) -> Result<Self, Box<dyn StdError + Sync + Send>> {
+        Ok(RawValue(Some(raw.to_vec())))
+    }
+
+    fn from_sql_null(_ty: &Type) -> Result<Self, Box<dyn StdError + Sync + Send>> {
+        Ok(RawValue(None))
+    }
+
+    // The value is decoded for its column's type by `CopyRow`.
+    fn accepts(_ty: &Type) -> bool {
+        true
+    }
+}
+
+/// Each of the model's values, in the binary format of its column's type from `types`.
+pub fn encode_row<M: ToRow>(model: &M, types: &[Type]) -> Result<Vec<RawValue>, Error> {
+    let columns = M::columns_list();
+    let params = model.params();
+    if params.len() != types.len() {
+        return Err(anyhow!(
+            "{} has {} columns, but the copy has {}",
+            type_name::<M>(),
+            params.len(),
+            types.len()
+        ));
+    }
+
+    params
+        .into_iter()
+        .zip(types)
+        .zip(columns)
+        .map(|((param, ty), column)| {
+            let mut out = BytesMut::new();
+            match param.to_sql_checked(ty, &mut out) {
+                Ok(IsNull::No) => Ok(RawValue(Some(out.to_vec()))),
+                Ok(IsNull::Yes) => Ok(RawValue(None)),
+                Err(e) => Err(anyhow!(e).context(format!("Could not write {}", column))),
+            }
+        })
+        .collect()
+}
+
+/// `COPY table (...) FROM STDIN BINARY`, for every column of the model.
+pub fn copy_in_sql<M: Model>(table: &str) -> String {
+    format!("COPY {} ({}) FROM STDIN BINARY", table, column_list::<M>())
+}
+
+/// `COPY table (...) TO STDOUT BINARY`, for every column of the model.
+pub fn copy_out_sql<M: Model>(table: &str) -> String {
+    format!("COPY {} ({}) TO STDOUT BINARY", table, column_list::<M>())
+}
+
+/// A row copied out of a table, with its columns named under the model's prefix so the model
+/// can be read from it.
+pub struct CopyRow<'c> {
+    columns: &'c [String],
+    types: &'c [Type],
+    values: Vec<RawValue>,
+}
+
+impl<'c> CopyRow<'c> {
+    /// `columns` are the names the `values` are read by, and `types` their types, in order.
+    pub fn new(columns: &'c [String], types: &'c [Type], values: Vec<RawValue>) -> Self {
+        CopyRow {
+            columns,
+            types,
+            values,
+        }
+    }
+
+    /// The values of a row read by `BinaryCopyOutStream`, as they were copied.
+    fn from_binary(
+        columns: &'c [String],
+        types: &'c [Type],
+        row: &BinaryCopyOutRow,
+    ) -> Result<Self, Error> {
+        let values = (0..types.len())
+            .map(|index| row.try_get::<RawValue>(index))
+            .collect::<Result<_, _>>()?;
+
+        Ok(CopyRow::new(columns, types, values))
+    }
+}
+
+impl TryGetRow for CopyRow<'_> {
+    fn try_get<'a, T: FromSql<'a>>(&'a self, index: &str) -> Result<T, Error> {
+        let position = column_position(self.columns, index)?;
+        let ty = &self.types[position];
+        if !T::accepts(ty) {
+            return Err(anyhow!(
+                "Could not read {}: a {} column can't be read as {}",
+                index,
+                ty,
+                type_name::<T>()
+            ));
+        }
+
+        T::from_sql_nullable(ty, self.values[position].0.as_deref())
+            .map_err(|e| anyhow!(e).context(format!("Could not read {}", index)))
+    }
+}
+
+/// Copies models in and out of a table, for `tokio_postgres` clients and transactions.
+pub trait CopyModels {
+    /// Copies `models` into `table`, returning how many rows were written.
+    fn copy_in_models<M: ToRow + Sync>(
+        &self,
+        table: &str,
+        models: &[M],
+    ) -> impl Future<Output = Result<u64, Error>> + Send;
+
+    /// Copies every row of `table` out as models.
+    fn copy_out_models<M: for<'r> ModelRef<'r> + Send>(
+        &self,
+        table: &str,
+    ) -> impl Future<Output = Result<Vec<M>, Error>> + Send;
+}
+
+macro_rules! impl_copy_models {
+    ($client:ty) => {
+        impl CopyModels for $client {
+            async fn copy_in_models<M: ToRow + Sync>(
+                &self,
+                table: &str,
+                models: &[M],
+            ) -> Result<u64, Error> {
+                let types = column_types(
+                    self.prepare(&format!(
+                        "SELECT {} FROM {} LIMIT 0",
+                        column_list::<M>(),
+                        table
+                    ))
+                    .await?,
+                );
+
+                let sink = self.copy_in(&copy_in_sql::<M>(table)).await?;
+                let mut writer = pin!(BinaryCopyInWriter::new(sink, &types));
+                for model in models {
+                    writer
+                        .as_mut()
+                        .write_raw(encode_row(model, &types)?)
+                        .await?;
+                }
+
+                Ok(writer.finish().await?)
+            }
+
+            async fn copy_out_models<M: for<'r> ModelRef<'r> + Send>(
+                &self,
+                table: &str,
+            ) -> Result<Vec<M>, Error> {
+                let types = column_types(
+                    self.prepare(&format!(
+                        "SELECT {} FROM {} LIMIT 0",
+                        column_list::<M>(),
+                        table
+                    ))
+                    .await?,
+                );
+                let columns = M::columns_list()
+                    .into_iter()
+                    .map(|column| alias(M::prefix(), column))
+                    .collect::<Vec<_>>();
+
+                let stream = self.copy_out(&copy_out_sql::<M>(table)).await?;
+                let mut rows = pin!(BinaryCopyOutStream::new(stream, &types));
+                let mut models = vec![];
+                while let Some(row) = poll_fn(|cx| rows.as_mut().poll_next(cx)).await {
+                    models.push(M::try_from_row(&CopyRow::from_binary(
+                        &columns, &types, &row?,
+                    )?)?);
+                }
+
+                Ok(models)
+            }
+        }
+    };
+}
+
+impl_copy_models!(Client);
+impl_copy_models!(Transaction<'_>);
+
+fn column_list<M: Model>() -> String {
+    let quoted = M::quoted_columns();
+
+    M::columns_list()
+        .into_iter()
+        .map(|column| column_sql(Dialect::Postgres, column, &quoted))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn column_types(statement: tokio_postgres::Statement) -> Vec<Type> {
+    statement
+        .columns()
+        .iter()
+        .map(|column| column.type_().clone())
+        .collect()
+}
+
+fn column_position(columns: &[String], index: &str) -> Result<usize, Error> {
+    columns
+        .iter()
+        .position(|column| column == index)
+        .ok_or_else(|| anyhow!("There is no column named {} in the row", index))
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_postgres::types::Type;
+    use usual_proc::UsualModel;
+
+    use super::{column_position, copy_in_sql, copy_out_sql, encode_row, CopyRow, RawValue};
+    use crate::base::{alias, Model, TryGetRow};
+
+    #[derive(UsualModel)]
+    struct Post {
+        #[usual(primary_key, generated)]
+        pub id: i64,
+        pub title: String,
+        #[usual(db_default)]
+        pub created_at: i64,
+    }
+
+    #[derive(Debug, PartialEq, UsualModel)]
+    struct Note {
+        pub id: i64,
+        pub body: Option<String>,
+    }
+
+    #[derive(UsualModel)]
+    struct Order {
+        pub id: i64,
+        #[usual(quote)]
+        pub order: i64,
+    }
+
+    #[test]
+    fn it_should_build_copy_statements() {
+        assert!(
+            copy_in_sql::<Post>("posts") == "COPY posts (id, title, created_at) FROM STDIN BINARY",
+            "It should copy in every column, including generated ones."
+        );
+        assert!(
+            copy_out_sql::<Post>("posts") == "COPY posts (id, title, created_at) TO STDOUT BINARY",
+            "It should copy out every column."
+        );
+        assert!(
+            copy_in_sql::<Order>("orders") == r#"COPY orders (id, "order") FROM STDIN BINARY"#,
+            "It should quote the columns that need it."
+        );
+    }
+
+    const POST_TYPES: [Type; 3] = [Type::INT8, Type::TEXT, Type::INT8];
+    const NOTE_TYPES: [Type; 2] = [Type::INT8, Type::TEXT];
+
+    /// The values of `Post { id: 1, title: "hi", created_at: 2 }`, as `COPY ... BINARY` has them.
+    fn post_values() -> Vec<RawValue> {
+        vec![
+            RawValue(Some(vec![0, 0, 0, 0, 0, 0, 0, 1])),
+            RawValue(Some(b"hi".to_vec())),
+            RawValue(Some(vec![0, 0, 0, 0, 0, 0, 0, 2])),
+        ]
+    }
+
+    fn columns<M: Model>() -> Vec<String> {
+        M::columns_list()
+            .into_iter()
+            .map(|column| alias(M::prefix(), column))
+            .collect()
+    }
+
+    #[test]
+    fn it_should_encode_each_value_for_its_column_type() {
+        let post = Post {
+            id: 1,
+            title: "hi".to_string(),
+            created_at: 2,
+        };
+
+        assert!(
+            encode_row(&post, &POST_TYPES).unwrap() == post_values(),
+            "It should encode every column in the binary format of its type."
+        );
+        assert!(
+            encode_row(&Note { id: 3, body: None }, &NOTE_TYPES).unwrap()
+                == vec![RawValue(Some(vec![0, 0, 0, 0, 0, 0, 0, 3])), RawValue(None)],
+            "It should encode a missing value as NULL."
+        );
+        assert!(
+            matches!(encode_row(&post, &[Type::INT8, Type::INT8, Type::INT8]), Err(e) if format!("{:#}", e).contains("title")),
+            "It should fail on a value that doesn't fit its column, naming the column."
+        );
+        assert!(
+            encode_row(&post, &NOTE_TYPES).is_err(),
+            "It should fail when the copy has a different number of columns."
+        );
+    }
+
+    #[test]
+    fn it_should_decode_copied_rows() {
+        let post_columns = columns::<Post>();
+        let note_columns = columns::<Note>();
+
+        let post =
+            Post::try_from_row(&CopyRow::new(&post_columns, &POST_TYPES, post_values())).unwrap();
+
+        assert!(
+            post.id == 1 && post.title == "hi" && post.created_at == 2,
+            "It should decode every column from the binary format of its type."
+        );
+        assert!(
+            Note::try_from_row(&CopyRow::new(
+                &note_columns,
+                &NOTE_TYPES,
+                vec![RawValue(Some(vec![0, 0, 0, 0, 0, 0, 0, 3])), RawValue(None)]
+            ))
+            .unwrap()
+                == Note { id: 3, body: None },
+            "It should decode NULL as a missing value."
+        );
+        assert!(
+            matches!(
+                Post::try_from_row(&CopyRow::new(&post_columns, &[Type::INT8, Type::INT8, Type::INT8], post_values())),
+                Err(e) if format!("{:#}", e).contains("Post__title")
+            ),
+            "It should fail to read a column of another type, naming the column."
+        );
+        assert!(
+            Post::try_from_row(&CopyRow::new(
+                &post_columns,
+                &POST_TYPES,
+                vec![
+                    RawValue(Some(vec![0, 1])),
+                    RawValue(Some(b"hi".to_vec())),
+                    RawValue(None)
+                ]
+            ))
+            .is_err(),
+            "It should fail on a malformed or NULL value for a required column."
+        );
+    }
+
+    #[test]
+    fn it_should_find_copied_columns_by_name() {
+        let columns = vec!["Post__id".to_string(), "Post__title".to_string()];
+
+        assert!(
+            column_position(&columns, "Post__title").unwrap() == 1,
+            "It should find a column by the name it's read with."
+        );
+        assert!(
+            column_position(&columns, "Post__body").is_err(),
+            "It should fail to find a column that wasn't copied."
+        );
+    }
+}
@@ -0,0 +1,322 @@
+//! Paging through huge results with a server-side cursor, a batch of models at a time:
+//!
+//! ```ignore
+//! let transaction = client.transaction().await?;
+//! let mut cursor = Cursor::<_, Post>::declare(&transaction, query!("SELECT {Post} FROM posts").as_str(), &[])
+//!     .await?
+//!     .batch_size(10_000);
+//!
+//! while let Some(posts) = cursor.next_batch().await? {
+//!     export(posts);
+//! }
+//! ```
+use std::{
+    future::Future,
+    marker::PhantomData,
+    pin::pin,
+    sync::atomic::{AtomicUsize, Ordering},
+    task::{Context, Waker},
+};
+
+use tokio_postgres::{GenericClient, Row};
+
+use crate::base::{Error, Hydrate, SqlParam, TryGetRow};
+
+/// The statements a [`Cursor`] runs. It's implemented for `tokio_postgres` clients and
+/// transactions, and can be implemented by a mock to test code that pages through a cursor.
+pub trait CursorTransport {
+    type Row: TryGetRow;
+
+    /// Runs a statement that doesn't return rows, like `DECLARE` or `CLOSE`.
+    fn execute(
+        &self,
+        sql: &str,
+        params: &[SqlParam<'_>],
+    ) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Runs a statement that returns rows, like `FETCH`.
+    fn query(&self, sql: &str) -> impl Future<Output = Result<Vec<Self::Row>, Error>> + Send;
+
+    /// Sends a statement without waiting for it to finish, for closing a cursor when it's dropped.
+    fn send(&self, sql: &str);
+}
+
+impl<C: GenericClient + Sync> CursorTransport for C {
+    type Row = Row;
+
+    async fn execute(&self, sql: &str, params: &[SqlParam<'_>]) -> Result<(), Error> {
+        GenericClient::execute(self, sql, params).await?;
+
+        Ok(())
+    }
+
+    async fn query(&self, sql: &str) -> Result<Vec<Row>, Error> {
+        Ok(GenericClient::query(self, sql, &[]).await?)
+    }
+
+    fn send(&self, sql: &str) {
+        // The statement is queued on the connection the first time the future is polled. The
+        // response is never read, which the connection allows.
+        let statement = pin!(GenericClient::batch_execute(self, sql));
+        let _ = statement.poll(&mut Context::from_waker(Waker::noop()));
+    }
+}
+
+static CURSOR_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// A `DECLARE`d cursor, read a batch at a time with `FETCH`. Cursors only live as long as the
+/// transaction they're declared in.
+///
+/// The cursor is closed once it runs out of rows, when fetching fails, or when it's dropped.
+pub struct Cursor<'t, T: CursorTransport, M> {
+    transport: &'t T,
+    name: String,
+    batch_size: usize,
+    open: bool,
+    model: PhantomData<fn() -> M>,
+}
+
+impl<'t, T: CursorTransport, M: Hydrate> Cursor<'t, T, M> {
+    /// Declares a cursor for `sql`, a `SELECT` for the columns of `M`.
+    pub async fn declare(
+        transport: &'t T,
+        sql: &str,
+        params: &[SqlParam<'_>],
+    ) -> Result<Self, Error> {
+        let name = format!(
+            "usual_cursor_{}",
+            CURSOR_COUNT.fetch_add(1, Ordering::Relaxed)
+        );
+
+        transport
+            .execute(
+                &format!("DECLARE {} NO SCROLL CURSOR FOR {}", name, sql),
+                params,
+            )
+            .await?;
+
+        Ok(Cursor {
+            transport,
+            name,
+            batch_size: 1000,
+            open: true,
+            model: PhantomData,
+        })
+    }
+
+    /// The cursor's name on the server, for running other statements against it.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// How many rows each [`Cursor::next_batch`] fetches, 1000 by default.
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// The next batch of models, or `None` once every row has been read.
+    pub async fn next_batch(&mut self) -> Result<Option<Vec<M>>, Error> {
+        if !self.open {
+            return Ok(None);
+        }
+
+        let sql = format!("FETCH {} FROM {}", self.batch_size, self.name);
+        let models = match self.transport.query(&sql).await {
+            Ok(rows) => rows.iter().map(M::hydrate).collect::<Result<Vec<_>, _>>(),
+            Err(e) => Err(e),
+        };
+
+        match models {
+            Ok(models) if models.is_empty() => {
+                self.close_now().await?;
+                Ok(None)
+            }
+            Ok(models) => Ok(Some(models)),
+            Err(e) => {
+                // The fetch's error is the one worth reporting, whether or not closing works.
+                let _ = self.close_now().await;
+                Err(e)
+            }
+        }
+    }
+
+    /// Closes the cursor, freeing it on the server before the transaction ends.
+    pub async fn close(mut self) -> Result<(), Error> {
+        self.close_now().await
+    }
+
+    async fn close_now(&mut self) -> Result<(), Error> {
+        if !self.open {
+            return Ok(());
+        }
+
+        self.open = false;
+        self.transport
+            .execute(&format!("CLOSE {}", self.name), &[])
+            .await
+    }
+}
+
+impl<T: CursorTransport, M> Drop for Cursor<'_, T, M> {
+    fn drop(&mut self) {
+        if self.open {
+            self.transport.send(&format!("CLOSE {}", self.name));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+    use std::sync::Mutex;
+    use usual_proc::UsualModel;
+
+    use super::{Cursor, CursorTransport};
+    use crate::{
+        base::{Error, Model, SqlParam, TryGetRow},
+        testing::MockRow,
+    };
+
+    #[derive(UsualModel)]
+    struct Post {
+        pub id: i64,
+    }
+
+    /// Hands out the given batches of rows, and records the statements it's asked to run.
+    #[derive(Default)]
+    struct MockTransport {
+        batches: Mutex<Vec<Vec<MockRow>>>,
+        fail_fetch: bool,
+        statements: Mutex<Vec<String>>,
+        sent: Mutex<Vec<String>>,
+    }
+
+    impl MockTransport {
+        fn with_batches(ids: &[&[i64]]) -> Self {
+            let batches = ids
+                .iter()
+                .rev()
+                .map(|batch| {
+                    batch
+                        .iter()
+                        .map(|id| MockRow::from_model(&Post { id: *id }))
+                        .collect()
+                })
+                .collect();
+
+            MockTransport {
+                batches: Mutex::new(batches),
+                ..Default::default()
+            }
+        }
+
+        fn statements(&self) -> Vec<String> {
+            self.statements.lock().unwrap().clone()
+        }
+    }
+
+    impl CursorTransport for MockTransport {
+        type Row = MockRow;
+
+        async fn execute(&self, sql: &str, _params: &[SqlParam<'_>]) -> Result<(), Error> {
+            self.statements.lock().unwrap().push(sql.to_owned());
+
+            Ok(())
+        }
+
+        async fn query(&self, sql: &str) -> Result<Vec<MockRow>, Error> {
+            self.statements.lock().unwrap().push(sql.to_owned());
+
+            if self.fail_fetch {
+                return Err(anyhow!("the connection was lost"));
+            }
+
+            Ok(self.batches.lock().unwrap().pop().unwrap_or_default())
+        }
+
+        fn send(&self, sql: &str) {
+            self.sent.lock().unwrap().push(sql.to_owned());
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_fetch_batches_until_the_rows_run_out() {
+        let transport = MockTransport::with_batches(&[&[1, 2], &[3]]);
+
+        let mut cursor =
+            Cursor::<_, Post>::declare(&transport, "SELECT id as Post__id FROM posts", &[])
+                .await
+                .unwrap()
+                .batch_size(2);
+        let name = cursor.name().to_owned();
+
+        let mut ids = vec![];
+        while let Some(posts) = cursor.next_batch().await.unwrap() {
+            ids.push(posts.iter().map(|post| post.id).collect::<Vec<_>>());
+        }
+
+        assert!(
+            ids == vec![vec![1, 2], vec![3]],
+            "It should hydrate each batch of rows."
+        );
+        assert!(
+            transport.statements()
+                == vec![
+                    format!(
+                        "DECLARE {} NO SCROLL CURSOR FOR SELECT id as Post__id FROM posts",
+                        name
+                    ),
+                    format!("FETCH 2 FROM {}", name),
+                    format!("FETCH 2 FROM {}", name),
+                    format!("FETCH 2 FROM {}", name),
+                    format!("CLOSE {}", name),
+                ],
+            "It should declare the cursor, fetch from it, and close it once it's empty."
+        );
+        assert!(
+            cursor.next_batch().await.unwrap().is_none(),
+            "It should not fetch from a closed cursor."
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_close_the_cursor_on_errors() {
+        let transport = MockTransport {
+            fail_fetch: true,
+            ..Default::default()
+        };
+
+        let mut cursor =
+            Cursor::<_, Post>::declare(&transport, "SELECT id as Post__id FROM posts", &[])
+                .await
+                .unwrap();
+        let name = cursor.name().to_owned();
+
+        assert!(
+            cursor.next_batch().await.is_err(),
+            "It should return the error from fetching."
+        );
+        assert!(
+            transport.statements().last() == Some(&format!("CLOSE {}", name)),
+            "It should close the cursor after an error."
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_close_the_cursor_when_dropped() {
+        let transport = MockTransport::with_batches(&[&[1]]);
+
+        let cursor =
+            Cursor::<_, Post>::declare(&transport, "SELECT id as Post__id FROM posts", &[])
+                .await
+                .unwrap();
+        let name = cursor.name().to_owned();
+        drop(cursor);
+
+        assert!(
+            *transport.sent.lock().unwrap() == vec![format!("CLOSE {}", name)],
+            "It should close a cursor that's dropped while open."
+        );
+    }
+}
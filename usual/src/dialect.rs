@@ -0,0 +1,260 @@
+//! The differences between databases that matter to the SQL `usual` generates.
+
+use anyhow::anyhow;
+
+use crate::base::Error;
+
+/// The database a generated statement is written for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Dialect {
+    #[default]
+    Postgres,
+    Sqlite,
+    MySql,
+}
+
+impl Dialect {
+    /// The most parameters a single statement can bind. For SQLite this is the limit since 3.32,
+    /// older versions only allow 999.
+    pub fn max_params(&self) -> usize {
+        match self {
+            Dialect::Postgres => 65535,
+            Dialect::Sqlite => 32766,
+            Dialect::MySql => 65535,
+        }
+    }
+
+    /// The placeholder for the parameter at `index`, counting from 1. MySQL's placeholders aren't
+    /// numbered, so its parameters have to be bound in the order they appear.
+    pub fn placeholder(&self, index: usize) -> String {
+        match self {
+            Dialect::Postgres => format!("${}", index),
+            Dialect::Sqlite => format!("?{}", index),
+            Dialect::MySql => "?".to_string(),
+        }
+    }
+
+    /// Quotes an identifier, so it can be a reserved word or keep its case.
+    pub fn quote(&self, identifier: &str) -> String {
+        match self {
+            Dialect::Postgres | Dialect::Sqlite => {
+                format!("\"{}\"", identifier.replace('"', "\"\""))
+            }
+            Dialect::MySql => format!("`{}`", identifier.replace('`', "``")),
+        }
+    }
+
+    /// Rewrites SQL written for Postgres, like the output of `query!`, for this dialect: `$n`
+    /// placeholders become the dialect's own, and `"quoted"` identifiers are quoted the dialect's
+    /// way. Quoted and dollar-quoted strings and comments are left alone.
+    ///
+    /// Along with the SQL, this returns the parameters to bind, as indexes into the original
+    /// parameters. They're only reordered for MySQL, where a parameter used twice is bound twice.
+    pub fn rewrite(&self, sql: &str) -> (String, Vec<usize>) {
+        let mut output = String::with_capacity(sql.len());
+        let mut order = vec![];
+        let mut highest = 0;
+        let mut rest = sql;
+
+        while let Some(c) = rest.chars().next() {
+            // How much of the rest is copied over as is.
+            let mut len = c.len_utf8();
+
+            match c {
+                '\'' => len = quoted_len(rest, 1, "'"),
+                '-' if rest.starts_with("--") => len = rest.find('\n').unwrap_or(rest.len()),
+                '/' if rest.starts_with("/*") => len = block_comment_len(rest),
+                '"' => {
+                    let mut identifier = String::new();
+                    let mut chars = rest[1..].char_indices().peekable();
+                    len = rest.len();
+                    while let Some((i, c)) = chars.next() {
+                        if c == '"' && chars.next_if(|(_, c)| *c == '"').is_none() {
+                            len = i + 2;
+                            break;
+                        }
+                        identifier.push(c);
+                    }
+
+                    output.push_str(&self.quote(&identifier));
+                    rest = &rest[len..];
+                    continue;
+                }
+                '$' if rest[1..].starts_with(|c: char| c.is_ascii_digit()) => {
+                    let digits = rest[1..]
+                        .find(|c: char| !c.is_ascii_digit())
+                        .unwrap_or(rest.len() - 1);
+
+                    let index = rest[1..=digits].parse::<usize>().unwrap_or(0);
+                    highest = highest.max(index);
+                    order.push(index.saturating_sub(1));
+                    output.push_str(&self.placeholder(index));
+                    rest = &rest[1 + digits..];
+                    continue;
+                }
+                // A `$` inside an identifier, like `a$b`, doesn't start a dollar-quoted string.
+                '$' if !output.ends_with(|c: char| c.is_alphanumeric() || c == '_') => {
+                    if let Some(tag_len) = dollar_quote_tag_len(rest) {
+                        len = quoted_len(rest, tag_len, &rest[..tag_len]);
+                    }
+                }
+                _ => {}
+            }
+
+            output.push_str(&rest[..len]);
+            rest = &rest[len..];
+        }
+
+        match self {
+            Dialect::MySql => (output, order),
+            Dialect::Postgres | Dialect::Sqlite => (output, (0..highest).collect()),
+        }
+    }
+
+    /// [`Dialect::rewrite`] for SQL without parameters attached, like the `String` from
+    /// `Model::insert_sql` or a `query!` without named parameters. MySQL binds parameters in the
+    /// order they appear, so for it this is an error unless the SQL uses `$1` to `$n` once each,
+    /// in order. Rewrite SQL with parameters attached with `Statement::dialect` instead.
+    pub fn rewrite_sql(&self, sql: &str) -> Result<String, Error> {
+        let (sql, order) = self.rewrite(sql);
+
+        if let Some((i, index)) = order.iter().enumerate().find(|(i, index)| i != *index) {
+            return Err(anyhow!(
+                "The SQL uses ${} where MySQL would bind ${}, rewrite it with its parameters instead",
+                index + 1,
+                i + 1
+            ));
+        }
+
+        Ok(sql)
+    }
+}
+
+/// The length of a quoted string at the start of `sql`, from its opening quote of `open` bytes to
+/// the end of its closing quote, or the end of `sql` if it isn't closed.
+fn quoted_len(sql: &str, open: usize, close: &str) -> usize {
+    sql[open..]
+        .find(close)
+        .map_or(sql.len(), |i| open + i + close.len())
+}
+
+/// The length of the `/* comment */` at the start of `sql`. Like in Postgres, comments nest.
+fn block_comment_len(sql: &str) -> usize {
+    let mut depth = 0;
+    let mut i = 0;
+
+    while i < sql.len() {
+        if sql[i..].starts_with("/*") {
+            depth += 1;
+            i += 2;
+        } else if sql[i..].starts_with("*/") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                return i;
+            }
+        } else {
+            i += sql[i..].chars().next().map_or(1, char::len_utf8);
+        }
+    }
+
+    sql.len()
+}
+
+/// The length of the tag of a dollar-quoted string at the start of `sql`, like `$$` or `$body$`.
+fn dollar_quote_tag_len(sql: &str) -> Option<usize> {
+    let name_len = sql[1..]
+        .find(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .unwrap_or(sql.len() - 1);
+
+    sql[1 + name_len..].starts_with('$').then_some(name_len + 2)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Dialect;
+
+    const SQL: &str = r#"SELECT "order", 'a $1 "b"' FROM posts WHERE "user" = $2 AND (author = $1 OR editor = $1)"#;
+
+    #[test]
+    fn it_should_rewrite_placeholders_and_quotes() {
+        assert!(
+            Dialect::Postgres.rewrite(SQL) == (SQL.to_string(), vec![0, 1]),
+            "It should leave Postgres alone."
+        );
+        assert!(
+            Dialect::Sqlite.rewrite(SQL)
+                == (
+                    r#"SELECT "order", 'a $1 "b"' FROM posts WHERE "user" = ?2 AND (author = ?1 OR editor = ?1)"#.to_string(),
+                    vec![0, 1]
+                ),
+            "It should number SQLite's placeholders."
+        );
+        assert!(
+            Dialect::MySql.rewrite(SQL)
+                == (
+                    r#"SELECT `order`, 'a $1 "b"' FROM posts WHERE `user` = ? AND (author = ? OR editor = ?)"#.to_string(),
+                    vec![1, 0, 0]
+                ),
+            "It should bind MySQL's parameters in the order they're used, and quote with backticks."
+        );
+    }
+
+    #[test]
+    fn it_should_leave_comments_and_dollar_quoted_strings_alone() {
+        let sql = "SELECT \"a\", $$ \"b\" $1 $$, $body$ $2 $$ $body$ -- \"c\" $3\nFROM posts /* \"d\" /* $4 */ $5 */ WHERE id = $1 AND \"price$\" = $2";
+
+        assert!(
+            Dialect::MySql.rewrite(sql)
+                == (
+                    "SELECT `a`, $$ \"b\" $1 $$, $body$ $2 $$ $body$ -- \"c\" $3\nFROM posts /* \"d\" /* $4 */ $5 */ WHERE id = ? AND `price$` = ?".to_string(),
+                    vec![0, 1]
+                ),
+            "It should only rewrite placeholders and identifiers outside of comments and strings."
+        );
+        assert!(
+            Dialect::Sqlite
+                .rewrite_sql("SELECT * FROM posts WHERE id = $1")
+                .unwrap()
+                == "SELECT * FROM posts WHERE id = ?1",
+            "It should rewrite SQL without parameters attached."
+        );
+    }
+
+    #[test]
+    fn it_should_refuse_to_reorder_mysql_parameters_it_cant_bind() {
+        let sql = "SELECT * FROM posts WHERE a = $2 AND b = $1";
+
+        assert!(
+            matches!(Dialect::MySql.rewrite_sql(sql), Err(e) if e.to_string() == "The SQL uses $2 where MySQL would bind $1, rewrite it with its parameters instead"),
+            "It should fail instead of binding MySQL's parameters in the wrong order."
+        );
+        assert!(
+            Dialect::MySql
+                .rewrite_sql("SELECT * FROM posts WHERE a = $1 OR b = $1")
+                .is_err(),
+            "It should fail when a parameter would have to be bound twice."
+        );
+        assert!(
+            Dialect::Sqlite.rewrite_sql(sql).unwrap()
+                == "SELECT * FROM posts WHERE a = ?2 AND b = ?1"
+                && Dialect::MySql
+                    .rewrite_sql("SELECT * FROM posts WHERE a = $1 AND b = $2")
+                    .unwrap()
+                    == "SELECT * FROM posts WHERE a = ? AND b = ?",
+            "It should rewrite numbered or in order placeholders."
+        );
+    }
+
+    #[test]
+    fn it_should_escape_quotes_in_identifiers() {
+        assert!(
+            Dialect::Postgres.quote(r#"a"b"#) == r#""a""b""#,
+            "It should double double quotes."
+        );
+        assert!(
+            Dialect::MySql.rewrite(r#"SELECT "a""b`c""#).0 == "SELECT `a\"b``c`",
+            "It should carry escaped quotes over to the new quoting."
+        );
+    }
+}
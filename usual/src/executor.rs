@@ -0,0 +1,271 @@
+//! Running queries and getting models back, with `tokio-postgres`:
+//!
+//! ```ignore
+//! use usual::executor::Executor;
+//!
+//! let posts = client.fetch_all::<Post>(query!("SELECT {Post} FROM posts").as_str(), &[]).await?;
+//! ```
+use std::future::Future;
+
+use anyhow::anyhow;
+use tokio_postgres::{GenericClient, Row};
+
+use crate::base::{Error, Hydrate, SqlParam, TryGetRow};
+
+/// Queries that return models, for anything that can run a query: a `tokio_postgres::Client`, a
+/// `Transaction`, or a pooled client. Rows are read with [`Hydrate::hydrate`], so a missing or
+/// mistyped column is returned as an error rather than a panic.
+///
+/// Only [`Executor::query`] and [`Executor::execute`] need implementing, so a mock returning
+/// `MockRow`s can stand in for a database in tests.
+pub trait Executor: Sync {
+    type Row: TryGetRow;
+
+    /// Every row the query returns, as is.
+    fn query(
+        &self,
+        sql: &str,
+        params: &[SqlParam<'_>],
+    ) -> impl Future<Output = Result<Vec<Self::Row>, Error>> + Send;
+
+    /// Runs the query, returning the number of rows it changed.
+    fn execute(
+        &self,
+        sql: &str,
+        params: &[SqlParam<'_>],
+    ) -> impl Future<Output = Result<u64, Error>> + Send;
+
+    /// Every row the query returns.
+    fn fetch_all<M: Hydrate>(
+        &self,
+        sql: &str,
+        params: &[SqlParam<'_>],
+    ) -> impl Future<Output = Result<Vec<M>, Error>> + Send {
+        async move {
+            self.query(sql, params)
+                .await?
+                .iter()
+                .map(M::hydrate)
+                .collect()
+        }
+    }
+
+    /// The single row the query returns. It's an error for the query to return no rows, or more
+    /// than one.
+    fn fetch_one<M: Hydrate>(
+        &self,
+        sql: &str,
+        params: &[SqlParam<'_>],
+    ) -> impl Future<Output = Result<M, Error>> + Send {
+        async move {
+            self.fetch_optional(sql, params)
+                .await?
+                .ok_or_else(|| anyhow!("The query returned no rows"))
+        }
+    }
+
+    /// The row the query returns, if any. It's an error for the query to return more than one.
+    fn fetch_optional<M: Hydrate>(
+        &self,
+        sql: &str,
+        params: &[SqlParam<'_>],
+    ) -> impl Future<Output = Result<Option<M>, Error>> + Send {
+        async move {
+            match self.query(sql, params).await?.as_slice() {
+                [] => Ok(None),
+                [row] => M::hydrate(row).map(Some),
+                _ => Err(anyhow!("The query returned more than one row")),
+            }
+        }
+    }
+}
+
+impl<C: GenericClient + Sync> Executor for C {
+    type Row = Row;
+
+    async fn query(&self, sql: &str, params: &[SqlParam<'_>]) -> Result<Vec<Row>, Error> {
+        Ok(GenericClient::query(self, sql, params).await?)
+    }
+
+    async fn execute(&self, sql: &str, params: &[SqlParam<'_>]) -> Result<u64, Error> {
+        Ok(GenericClient::execute(self, sql, params).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+    use std::sync::Mutex;
+    use usual_proc::UsualModel;
+
+    use super::Executor;
+    use crate::{
+        base::{Error, Model, SqlParam, TryGetRow},
+        testing::MockRow,
+    };
+
+    #[derive(Debug, UsualModel)]
+    struct Post {
+        pub id: i64,
+        pub title: String,
+    }
+
+    #[derive(Debug, UsualModel)]
+    struct Author {
+        pub name: String,
+    }
+
+    /// Returns rows built by `rows`, and records the statements it's asked to run.
+    struct MockExecutor {
+        rows: fn() -> Vec<MockRow>,
+        statements: Mutex<Vec<String>>,
+    }
+
+    impl MockExecutor {
+        fn new(rows: fn() -> Vec<MockRow>) -> Self {
+            MockExecutor {
+                rows,
+                statements: Mutex::new(vec![]),
+            }
+        }
+    }
+
+    impl Executor for MockExecutor {
+        type Row = MockRow;
+
+        async fn query(&self, sql: &str, _params: &[SqlParam<'_>]) -> Result<Vec<MockRow>, Error> {
+            self.statements.lock().unwrap().push(sql.to_owned());
+
+            Ok((self.rows)())
+        }
+
+        async fn execute(&self, sql: &str, _params: &[SqlParam<'_>]) -> Result<u64, Error> {
+            self.statements.lock().unwrap().push(sql.to_owned());
+
+            Err(anyhow!("the connection was lost"))
+        }
+    }
+
+    fn post(id: i64) -> MockRow {
+        MockRow::from_model(&Post {
+            id,
+            title: format!("title {}", id),
+        })
+    }
+
+    fn no_rows() -> Vec<MockRow> {
+        vec![]
+    }
+
+    fn one_row() -> Vec<MockRow> {
+        vec![post(1).set("Author__name", "Ada".to_string())]
+    }
+
+    fn two_rows() -> Vec<MockRow> {
+        vec![post(1), post(2)]
+    }
+
+    fn mistyped_row() -> Vec<MockRow> {
+        vec![MockRow::new()
+            .set("Post__id", "one".to_string())
+            .set("Post__title", "title".to_string())]
+    }
+
+    #[tokio::test]
+    async fn it_should_fetch_every_row() {
+        let executor = MockExecutor::new(two_rows);
+
+        let posts = executor
+            .fetch_all::<Post>(
+                "SELECT id as Post__id, title as Post__title FROM posts",
+                &[],
+            )
+            .await
+            .unwrap();
+
+        assert!(
+            posts.iter().map(|post| post.id).collect::<Vec<_>>() == vec![1, 2],
+            "It should hydrate every row the query returns."
+        );
+        assert!(
+            *executor.statements.lock().unwrap()
+                == vec!["SELECT id as Post__id, title as Post__title FROM posts"],
+            "It should run the query it's given."
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_fetch_exactly_one_row() {
+        let (post, author) = MockExecutor::new(one_row)
+            .fetch_one::<(Post, Author)>("SELECT ...", &[])
+            .await
+            .unwrap();
+
+        assert!(
+            post.title == "title 1" && author.name == "Ada",
+            "It should hydrate tuples of models from the single row."
+        );
+        assert!(
+            MockExecutor::new(no_rows)
+                .fetch_one::<Post>("SELECT ...", &[])
+                .await
+                .is_err(),
+            "It should fail when there are no rows."
+        );
+        assert!(
+            MockExecutor::new(two_rows)
+                .fetch_one::<Post>("SELECT ...", &[])
+                .await
+                .is_err(),
+            "It should fail when there's more than one row."
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_fetch_an_optional_row() {
+        assert!(
+            MockExecutor::new(no_rows)
+                .fetch_optional::<Post>("SELECT ...", &[])
+                .await
+                .unwrap()
+                .is_none(),
+            "It should return nothing when there are no rows."
+        );
+        assert!(
+            MockExecutor::new(one_row)
+                .fetch_optional::<Post>("SELECT ...", &[])
+                .await
+                .unwrap()
+                .map(|post| post.id)
+                == Some(1),
+            "It should return the single row."
+        );
+        assert!(
+            MockExecutor::new(two_rows)
+                .fetch_optional::<Post>("SELECT ...", &[])
+                .await
+                .is_err(),
+            "It should fail when there's more than one row."
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_return_hydration_and_query_errors() {
+        let error = MockExecutor::new(mistyped_row)
+            .fetch_all::<Post>("SELECT ...", &[])
+            .await
+            .unwrap_err();
+
+        assert!(
+            format!("{:#}", error).contains("Post__id"),
+            "It should return an error naming the column that couldn't be read, instead of panicking."
+        );
+        assert!(
+            MockExecutor::new(no_rows)
+                .execute("DELETE FROM posts", &[])
+                .await
+                .is_err(),
+            "It should return the error from running the statement."
+        );
+    }
+}
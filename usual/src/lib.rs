@@ -1,2 +1,19 @@
+// Lets code generated by `usual-proc` refer to `::usual` from inside this crate too.
+extern crate self as usual;
+
 pub mod base;
+#[cfg(feature = "tokio-postgres")]
+pub mod copy;
+#[cfg(feature = "tokio-postgres")]
+pub mod cursor;
+pub mod dialect;
+#[cfg(feature = "tokio-postgres")]
+pub mod executor;
+#[cfg(feature = "tokio-postgres")]
+pub mod stream;
+pub mod testing;
+pub mod write;
+
+#[cfg(feature = "serde")]
+pub use serde;
 pub use usual_proc::*;
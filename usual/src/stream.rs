@@ -0,0 +1,198 @@
+//! Reading models from a stream of rows, like the `RowStream` returned by
+//! `tokio_postgres::Client::query_raw`, without buffering every row first:
+//!
+//! ```ignore
+//! let rows = client.query_raw(query!("SELECT {Post} FROM posts").as_str(), params).await?;
+//! let mut posts = ModelStream::<Post, _>::new(rows);
+//!
+//! while let Some(post) = posts.next().await {
+//!     export(post?);
+//! }
+//! ```
+use std::{
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use futures_core::Stream;
+use tokio_postgres::Row;
+
+use crate::base::{Error, Hydrate, TryGetRow};
+
+/// A stream of models, read one at a time from a stream of rows. An error from the row stream,
+/// or a row that can't be read, is passed along as an `Err` item.
+///
+/// The rows are `tokio_postgres` rows by default, but can be anything implementing
+/// [`TryGetRow`], like `MockRow`s in tests.
+pub struct ModelStream<M, S, R = Row, F = fn(&R) -> Result<M, Error>> {
+    rows: Pin<Box<S>>,
+    hydrate: F,
+    model: PhantomData<fn(&R) -> M>,
+}
+
+impl<M: Hydrate, S, R: TryGetRow> ModelStream<M, S, R> {
+    /// Reads each row with [`Hydrate::hydrate`], so `M` can be a model or a tuple of models.
+    pub fn new<E>(rows: S) -> Self
+    where
+        S: Stream<Item = Result<R, E>>,
+    {
+        ModelStream::with(rows, |row| M::hydrate(row))
+    }
+}
+
+impl<M, S, R, F: FnMut(&R) -> Result<M, Error>> ModelStream<M, S, R, F> {
+    /// Reads each row with `hydrate`, for example a `partial!`:
+    /// `ModelStream::with(rows, |row| Ok(partial!(Post, title as String)(row)))`.
+    pub fn with<E>(rows: S, hydrate: F) -> Self
+    where
+        S: Stream<Item = Result<R, E>>,
+    {
+        ModelStream {
+            rows: Box::pin(rows),
+            hydrate,
+            model: PhantomData,
+        }
+    }
+}
+
+impl<M, S, R, E, F> Stream for ModelStream<M, S, R, F>
+where
+    S: Stream<Item = Result<R, E>>,
+    E: Into<Error>,
+    F: FnMut(&R) -> Result<M, Error> + Unpin,
+{
+    type Item = Result<M, Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        match this.rows.as_mut().poll_next(cx) {
+            Poll::Ready(Some(Ok(row))) => Poll::Ready(Some((this.hydrate)(&row))),
+            Poll::Ready(Some(Err(e))) => Poll::Ready(Some(Err(e.into()))),
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.rows.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+    use futures_core::Stream;
+    use std::{
+        pin::Pin,
+        task::{Context, Poll, Waker},
+    };
+    use usual_proc::{partial, UsualModel};
+
+    use super::ModelStream;
+    use crate::{
+        base::{Error, Model, TryGetRow},
+        testing::MockRow,
+    };
+
+    #[derive(Debug, UsualModel)]
+    struct Post {
+        pub id: i64,
+        pub title: String,
+    }
+
+    /// Rows handed out in order, with no database behind them.
+    struct Rows(Vec<Result<MockRow, Error>>);
+
+    impl Rows {
+        fn new(mut rows: Vec<Result<MockRow, Error>>) -> Self {
+            rows.reverse();
+            Rows(rows)
+        }
+    }
+
+    impl Stream for Rows {
+        type Item = Result<MockRow, Error>;
+
+        fn poll_next(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+            Poll::Ready(self.get_mut().0.pop())
+        }
+    }
+
+    fn post(id: i64) -> MockRow {
+        MockRow::from_model(&Post {
+            id,
+            title: format!("title {}", id),
+        })
+    }
+
+    fn collect<S: Stream + Unpin>(mut stream: S) -> Vec<S::Item> {
+        let mut cx = Context::from_waker(Waker::noop());
+        let mut items = vec![];
+        while let Poll::Ready(Some(item)) = Pin::new(&mut stream).poll_next(&mut cx) {
+            items.push(item);
+        }
+
+        items
+    }
+
+    #[test]
+    fn it_should_read_models_from_rows() {
+        let posts = collect(ModelStream::<Post, _, _>::new(Rows::new(vec![
+            Ok(post(1)),
+            Ok(post(2)),
+        ])));
+
+        assert!(
+            posts
+                .iter()
+                .map(|post| post.as_ref().unwrap().id)
+                .collect::<Vec<_>>()
+                == vec![1, 2],
+            "It should read a model from each row, in order."
+        );
+    }
+
+    #[test]
+    fn it_should_pass_hydration_errors_along() {
+        let posts = collect(ModelStream::<Post, _, _>::new(Rows::new(vec![
+            Ok(MockRow::new().set("Post__id", 1_i64)),
+            Ok(post(2)),
+        ])));
+
+        assert!(
+            matches!(&posts[0], Err(e) if format!("{:#}", e).contains("Post__title")),
+            "It should pass a row that can't be read along as an error naming the column."
+        );
+        assert!(
+            matches!(&posts[1], Ok(post) if post.id == 2),
+            "It should keep reading rows after one that can't be read."
+        );
+    }
+
+    #[test]
+    fn it_should_read_rows_with_a_function() {
+        let titles = collect(ModelStream::with(Rows::new(vec![Ok(post(1))]), |row| {
+            Ok(partial!(Post, title as String)(row).title)
+        }));
+
+        assert!(
+            matches!(&titles[..], [Ok(title)] if title == "title 1"),
+            "It should read each row with the given function, like a partial."
+        );
+    }
+
+    #[test]
+    fn it_should_pass_row_errors_along() {
+        let models = collect(ModelStream::with(
+            Rows::new(vec![Err(anyhow!("connection lost"))]),
+            |_row| Ok(()),
+        ));
+
+        assert!(
+            models.len() == 1 && matches!(&models[0], Err(e) if e.to_string() == "connection lost"),
+            "It should pass errors from the row stream along, and end when it ends."
+        );
+    }
+}
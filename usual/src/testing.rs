@@ -0,0 +1,357 @@
+//! Utilities for testing code that reads models, without a database.
+//!
+//! [`MockRow`] implements [`TryGetRow`] over values set up front, and records which columns were
+//! read from it:
+//!
+//! ```ignore
+//! let row = MockRow::new()
+//!     .set_field::<Post, _>("id", 1_i64)
+//!     .set_field::<Post, _>("title", "A title".to_string());
+//!
+//! let post = partial!(Post, title as String)(&row);
+//!
+//! assert_eq!(row.read_columns(), vec!["Post__title"]);
+//! ```
+use anyhow::{anyhow, Error};
+use std::{cell::RefCell, collections::HashMap};
+
+use crate::base::{alias, Model, ToRow, TryGetRow};
+
+#[cfg(not(feature = "tokio-postgres"))]
+use std::any::Any;
+
+#[cfg(feature = "tokio-postgres")]
+use tokio_postgres::types::{FromSql, IsNull, ToSql, Type, WrongType};
+
+/// Without a database, a value is kept as is, and each read hands out a copy of it.
+#[cfg(not(feature = "tokio-postgres"))]
+type MockValue = Box<dyn Any>;
+
+/// With `tokio-postgres`, a value is kept encoded the way Postgres would send it, so reading it
+/// goes through `FromSql` exactly like a real row.
+#[cfg(feature = "tokio-postgres")]
+struct MockValue {
+    ty: Type,
+    raw: Option<Vec<u8>>,
+}
+
+/// The types a value set on a [`MockRow`] is tried as, in order. The first one the value's
+/// `ToSql` implementation accepts is the type it's stored with.
+#[cfg(feature = "tokio-postgres")]
+const CANDIDATE_TYPES: &[Type] = &[
+    Type::BOOL,
+    Type::INT2,
+    Type::INT4,
+    Type::INT8,
+    Type::OID,
+    Type::FLOAT4,
+    Type::FLOAT8,
+    Type::TEXT,
+    Type::BYTEA,
+    Type::CHAR,
+    Type::TIMESTAMPTZ,
+    Type::TIMESTAMP,
+    Type::DATE,
+    Type::TIME,
+    Type::JSONB,
+    Type::BOOL_ARRAY,
+    Type::INT2_ARRAY,
+    Type::INT4_ARRAY,
+    Type::INT8_ARRAY,
+    Type::FLOAT4_ARRAY,
+    Type::FLOAT8_ARRAY,
+    Type::TEXT_ARRAY,
+    Type::BYTEA_ARRAY,
+];
+
+/// A row built in memory, for testing code that hydrates models.
+#[derive(Default)]
+pub struct MockRow {
+    values: HashMap<String, MockValue>,
+    reads: RefCell<Vec<String>>,
+}
+
+impl MockRow {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A row holding every column of `model`, under the model's prefix, the way
+    /// `query!("SELECT {Model} ...")` would return it.
+    pub fn from_model<M: ToRow>(model: &M) -> Self {
+        Self::from_model_with_prefix(M::prefix(), model)
+    }
+
+    /// A row holding every column of `model` under `prefix`.
+    pub fn from_model_with_prefix<M: ToRow>(prefix: &str, model: &M) -> Self {
+        let mut row = Self::new();
+        for (column, value) in model.to_row() {
+            row.insert_param(alias(prefix, column), value);
+        }
+
+        row
+    }
+
+    /// Sets the column of the field `field` of the model `M`, using the model's prefix, so a field
+    /// marked `#[usual(rename = "...")]` is stored under its column.
+    ///
+    /// Panics if `M` has no such field.
+    #[cfg(not(feature = "tokio-postgres"))]
+    pub fn set_field<M: Model, T: Any + Clone>(self, field: &str, value: T) -> Self {
+        self.set(&alias(M::prefix(), column_for_field::<M>(field)), value)
+    }
+
+    /// Sets the column of the field `field` of the model `M`, using the model's prefix, so a field
+    /// marked `#[usual(rename = "...")]` is stored under its column.
+    ///
+    /// Panics if `M` has no such field.
+    #[cfg(feature = "tokio-postgres")]
+    pub fn set_field<M: Model, T: ToSql + Sync>(self, field: &str, value: T) -> Self {
+        self.set(&alias(M::prefix(), column_for_field::<M>(field)), value)
+    }
+
+    /// Sets a column by its full name, as it appears in the query's output.
+    #[cfg(not(feature = "tokio-postgres"))]
+    pub fn set<T: Any + Clone>(mut self, column: &str, value: T) -> Self {
+        self.values.insert(column.to_owned(), Box::new(value));
+        self
+    }
+
+    /// Sets a column by its full name, as it appears in the query's output.
+    ///
+    /// Panics if the value's type isn't one of the common Postgres types; use
+    /// [`MockRow::set_raw`] for anything else.
+    #[cfg(feature = "tokio-postgres")]
+    pub fn set<T: ToSql + Sync>(mut self, column: &str, value: T) -> Self {
+        self.insert_param(column.to_owned(), &value);
+        self
+    }
+
+    /// Sets a column to an already encoded value of the Postgres type `ty`, or to `NULL`.
+    #[cfg(feature = "tokio-postgres")]
+    pub fn set_raw(mut self, column: &str, ty: Type, raw: Option<Vec<u8>>) -> Self {
+        self.values.insert(column.to_owned(), MockValue { ty, raw });
+        self
+    }
+
+    /// The columns read so far, in the order they were read.
+    pub fn read_columns(&self) -> Vec<String> {
+        self.reads.borrow().clone()
+    }
+
+    pub fn was_read(&self, column: &str) -> bool {
+        self.reads.borrow().iter().any(|read| read == column)
+    }
+
+    /// The columns that were set but haven't been read, sorted by name.
+    pub fn unread_columns(&self) -> Vec<String> {
+        let mut unread = self
+            .values
+            .keys()
+            .filter(|column| !self.was_read(column))
+            .cloned()
+            .collect::<Vec<_>>();
+        unread.sort();
+
+        unread
+    }
+
+    #[cfg(not(feature = "tokio-postgres"))]
+    fn insert_param(&mut self, column: String, value: &dyn crate::base::AnyParam) {
+        self.values.insert(column, value.to_any());
+    }
+
+    #[cfg(feature = "tokio-postgres")]
+    fn insert_param(&mut self, column: String, value: &(dyn ToSql + Sync)) {
+        for ty in CANDIDATE_TYPES {
+            let mut buf = Default::default();
+            match value.to_sql_checked(ty, &mut buf) {
+                Ok(IsNull::No) => {
+                    self.values.insert(
+                        column,
+                        MockValue {
+                            ty: ty.clone(),
+                            raw: Some(buf.to_vec()),
+                        },
+                    );
+                    return;
+                }
+                Ok(IsNull::Yes) => {
+                    self.values.insert(
+                        column,
+                        MockValue {
+                            ty: ty.clone(),
+                            raw: None,
+                        },
+                    );
+                    return;
+                }
+                Err(e) if e.is::<WrongType>() => continue,
+                Err(e) => panic!("Could not encode the value for {}: {}", column, e),
+            }
+        }
+
+        panic!(
+            "The value for {} isn't one of the types MockRow knows, use `MockRow::set_raw` instead.",
+            column
+        );
+    }
+
+    fn read(&self, index: &str) -> Result<&MockValue, Error> {
+        self.reads.borrow_mut().push(index.to_owned());
+
+        self.values
+            .get(index)
+            .ok_or_else(|| anyhow!("There is no column named {} in the row", index))
+    }
+}
+
+/// The column `M` reads `field` from.
+fn column_for_field<M: Model>(field: &str) -> &'static str {
+    M::fields_list()
+        .into_iter()
+        .position(|name| name == field)
+        .map(|index| M::columns_list()[index])
+        .unwrap_or_else(|| {
+            panic!(
+                "{} has no field named {}",
+                std::any::type_name::<M>(),
+                field
+            )
+        })
+}
+
+#[cfg(not(feature = "tokio-postgres"))]
+impl TryGetRow for MockRow {
+    fn try_get<T: 'static + Clone>(&self, index: &str) -> Result<T, Error> {
+        self.read(index)?
+            .downcast_ref::<T>()
+            .cloned()
+            .ok_or_else(|| {
+                anyhow!(
+                    "Attempted to get type {} for {}, but was not the correct type",
+                    std::any::type_name::<T>(),
+                    index,
+                )
+            })
+    }
+}
+
+#[cfg(feature = "tokio-postgres")]
+impl TryGetRow for MockRow {
+    fn try_get<'a, T: FromSql<'a>>(&'a self, index: &str) -> Result<T, Error> {
+        let value = self.read(index)?;
+
+        if !T::accepts(&value.ty) {
+            return Err(anyhow!(
+                "Attempted to get type {} for {}, but the column is a {}",
+                std::any::type_name::<T>(),
+                index,
+                value.ty,
+            ));
+        }
+
+        T::from_sql_nullable(&value.ty, value.raw.as_deref()).map_err(|e| anyhow!(e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use usual_proc::{partial, UsualModel};
+
+    use super::MockRow;
+    use crate::base::{Model, TryGetRow};
+
+    #[derive(Clone, Debug, PartialEq, UsualModel)]
+    struct Post {
+        pub id: i64,
+        pub title: String,
+    }
+
+    #[test]
+    fn it_should_hydrate_models_from_fields() {
+        let row = MockRow::new()
+            .set_field::<Post, _>("id", 1_i64)
+            .set_field::<Post, _>("title", "A title".to_string());
+
+        let post = Post::from_row(&row);
+
+        assert!(
+            post.id == 1 && post.title == "A title",
+            "It should hydrate a model from the fields set on the row."
+        );
+    }
+
+    #[derive(Clone, Debug, PartialEq, UsualModel)]
+    struct Page {
+        pub id: i64,
+        #[usual(rename = "page_title")]
+        pub title: String,
+    }
+
+    #[test]
+    fn it_should_set_renamed_fields_under_their_column() {
+        let row = MockRow::new()
+            .set_field::<Page, _>("id", 1_i64)
+            .set_field::<Page, _>("title", "A title".to_string());
+
+        let page = Page::from_row(&row);
+
+        assert!(
+            page.title == "A title",
+            "It should hydrate a renamed field set by its field name."
+        );
+        assert!(
+            row.was_read("Page__page_title"),
+            "It should store a renamed field under its column."
+        );
+    }
+
+    #[test]
+    fn it_should_round_trip_models() {
+        let post = Post {
+            id: 2,
+            title: "Another title".to_string(),
+        };
+
+        let row = MockRow::from_model(&post);
+
+        assert!(
+            Post::from_row(&row) == post,
+            "It should hydrate the same model the row was built from."
+        );
+    }
+
+    #[test]
+    fn it_should_record_the_columns_read() {
+        let row = MockRow::from_model(&Post {
+            id: 3,
+            title: "Title".to_string(),
+        });
+
+        let _ = (partial!(Post, title as String))(&row);
+
+        assert!(
+            row.read_columns() == vec!["Post__title"],
+            "It should record the columns that were read."
+        );
+        assert!(
+            row.unread_columns() == vec!["Post__id"],
+            "It should report the columns that weren't read."
+        );
+    }
+
+    #[test]
+    fn it_should_fail_to_get_missing_or_mistyped_columns() {
+        let row = MockRow::new().set("Post__id", 4_i64);
+
+        assert!(
+            row.try_get::<String>("Post__id").is_err(),
+            "It should fail to get a column as the wrong type."
+        );
+        assert!(
+            row.try_get::<i64>("Post__title").is_err(),
+            "It should fail to get a column that wasn't set."
+        );
+    }
+}
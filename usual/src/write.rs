@@ -0,0 +1,957 @@
+//! Statements that write models, built from the same definitions used to read them.
+use std::ops::Deref;
+
+use anyhow::anyhow;
+
+use crate::{
+    base::{Error, Model, OwnedParam, SqlParam, ToRow},
+    dialect::Dialect,
+};
+
+/// A generated statement and the parameters to run it with.
+pub struct Statement<'a> {
+    pub sql: String,
+    pub params: Vec<SqlParam<'a>>,
+}
+
+impl<'a> Statement<'a> {
+    /// Rewrites a statement written for Postgres, like one from `query!` with named parameters,
+    /// for another dialect. See [`Dialect::rewrite`]. It's an error for the SQL to use a `$n`
+    /// without a parameter to bind to it.
+    pub fn dialect(self, dialect: Dialect) -> Result<Self, Error> {
+        let (sql, order) = dialect.rewrite(&self.sql);
+        let params = order
+            .into_iter()
+            .map(|index| {
+                self.params.get(index).copied().ok_or_else(|| {
+                    anyhow!(
+                        "The statement uses ${}, which has no parameter to bind",
+                        index + 1
+                    )
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Statement { sql, params })
+    }
+}
+
+/// Inserts many models with as few statements as possible, for example:
+///
+/// ```ignore
+/// for statement in BatchInsert::new("posts", &posts).returning().statements()? {
+///     let rows = client.query(statement.sql.as_str(), &statement.params).await?;
+/// }
+/// ```
+///
+/// Each statement inserts as many rows as fit under the dialect's parameter limit.
+pub struct BatchInsert<'a, M: ToRow> {
+    table: &'a str,
+    models: &'a [M],
+    dialect: Dialect,
+    max_params: Option<usize>,
+    returning: bool,
+}
+
+impl<'a, M: ToRow> BatchInsert<'a, M> {
+    pub fn new(table: &'a str, models: &'a [M]) -> Self {
+        BatchInsert {
+            table,
+            models,
+            dialect: Dialect::default(),
+            max_params: None,
+            returning: false,
+        }
+    }
+
+    pub fn dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+
+    /// Overrides the dialect's parameter limit, for example `999` for SQLite before 3.32.
+    pub fn max_params(mut self, max_params: usize) -> Self {
+        self.max_params = Some(max_params);
+        self
+    }
+
+    /// Returns every column of the inserted rows, under the model's prefix. MySQL can't return
+    /// rows, so building the statements for it is an error.
+    pub fn returning(mut self) -> Self {
+        self.returning = true;
+        self
+    }
+
+    /// The `INSERT` statements, or an error if they can't be written for the dialect.
+    pub fn statements(&self) -> Result<Vec<Statement<'a>>, Error> {
+        let columns = M::insert_columns();
+        let returning = returning_columns::<M>(self.dialect, self.returning, self.table)?;
+
+        let max_params = self.max_params.unwrap_or_else(|| self.dialect.max_params());
+
+        // Without any columns to write, each row needs its own `DEFAULT VALUES` statement.
+        let rows_per_statement = match columns.len() {
+            0 => 1,
+            count => (max_params / count).max(1),
+        };
+
+        Ok(self
+            .models
+            .chunks(rows_per_statement)
+            .map(|chunk| Statement {
+                sql: insert_sql(
+                    self.dialect,
+                    self.table,
+                    &columns,
+                    &M::quoted_columns(),
+                    chunk.len(),
+                    returning.as_deref(),
+                ),
+                params: chunk
+                    .iter()
+                    .flat_map(|model| model.insert_params())
+                    .collect(),
+            })
+            .collect())
+    }
+}
+
+/// Inserts a model, or updates the row it conflicts with, for example:
+///
+/// ```ignore
+/// let statement = Upsert::new("posts", &post).on_conflict(&["slug"]).statement()?;
+/// let row = client.query_one(statement.sql.as_str(), &statement.params).await?;
+/// ```
+///
+/// Unlike [`Model::insert_sql`], a `#[usual(generated)]` primary key is written when it's part of
+/// the conflict target, since the row can only conflict on it if it's given. Otherwise, like
+/// fields marked `#[usual(db_default)]`, it's left to the database. An existing row never has its
+/// key or conflict target changed.
+pub struct Upsert<'a, M: ToRow> {
+    table: &'a str,
+    model: &'a M,
+    dialect: Dialect,
+    conflict_fields: Option<Vec<&'a str>>,
+    excluded_fields: Vec<&'a str>,
+    do_nothing: bool,
+    returning: bool,
+}
+
+impl<'a, M: ToRow> Upsert<'a, M> {
+    pub fn new(table: &'a str, model: &'a M) -> Self {
+        Upsert {
+            table,
+            model,
+            dialect: Dialect::default(),
+            conflict_fields: None,
+            excluded_fields: vec![],
+            do_nothing: false,
+            returning: false,
+        }
+    }
+
+    pub fn dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+
+    /// The fields whose unique constraint the insert may conflict with. Defaults to the fields
+    /// marked `#[usual(primary_key)]`.
+    pub fn on_conflict(mut self, fields: &[&'a str]) -> Self {
+        self.conflict_fields = Some(fields.to_vec());
+        self
+    }
+
+    /// Fields that keep their current value when the row already exists.
+    pub fn exclude(mut self, fields: &[&'a str]) -> Self {
+        self.excluded_fields.extend_from_slice(fields);
+        self
+    }
+
+    /// Leaves an existing row alone, instead of updating it.
+    pub fn do_nothing(mut self) -> Self {
+        self.do_nothing = true;
+        self
+    }
+
+    /// Returns every column of the inserted or updated row, under the model's prefix. Rows left
+    /// alone by [`Upsert::do_nothing`] aren't returned. MySQL can't return rows, so building the
+    /// statement for it is an error.
+    pub fn returning(mut self) -> Self {
+        self.returning = true;
+        self
+    }
+
+    /// The upsert, or an error if it can't be written for the dialect or has no conflict target.
+    pub fn statement(&self) -> Result<Statement<'a>, Error> {
+        let returning = returning_columns::<M>(self.dialect, self.returning, self.table)?;
+        let primary_key = M::primary_key_columns();
+        let insert_columns = M::insert_columns();
+        let conflict_columns = match &self.conflict_fields {
+            Some(fields) => columns_for_fields::<M>(fields),
+            None => primary_key.clone(),
+        };
+        let (columns, params): (Vec<_>, Vec<_>) = self
+            .model
+            .to_row()
+            .into_iter()
+            .filter(|(column, _)| {
+                insert_columns.contains(column)
+                    || (primary_key.contains(column) && conflict_columns.contains(column))
+            })
+            .unzip();
+        let excluded_columns = columns_for_fields::<M>(&self.excluded_fields);
+        let updated_columns = columns
+            .iter()
+            .filter(|column| {
+                !primary_key.contains(column)
+                    && !conflict_columns.contains(column)
+                    && !excluded_columns.contains(column)
+            })
+            .collect::<Vec<_>>();
+
+        let quoted = M::quoted_columns();
+        let column_sql = |column: &str| column_sql(self.dialect, column, &quoted);
+
+        let mut sql = insert_sql(self.dialect, self.table, &columns, &quoted, 1, None);
+        if self.dialect == Dialect::MySql {
+            // MySQL conflicts on any unique key.
+            if self.do_nothing || updated_columns.is_empty() {
+                sql.replace_range(.."INSERT".len(), "INSERT IGNORE");
+            } else {
+                let assignments = updated_columns
+                    .iter()
+                    .map(|column| {
+                        let column = column_sql(column);
+                        format!("{} = VALUES({})", column, column)
+                    })
+                    .collect::<Vec<_>>();
+                sql.push_str(&format!(
+                    " ON DUPLICATE KEY UPDATE {}",
+                    assignments.join(", ")
+                ));
+            }
+
+            return Ok(Statement { sql, params });
+        }
+
+        // Without a target, `DO NOTHING` skips rows conflicting with any unique constraint.
+        sql.push_str(" ON CONFLICT");
+        if !conflict_columns.is_empty() {
+            let conflict_columns = conflict_columns
+                .iter()
+                .map(|column| column_sql(column))
+                .collect::<Vec<_>>();
+            sql.push_str(&format!(" ({})", conflict_columns.join(", ")));
+        }
+
+        if self.do_nothing || updated_columns.is_empty() {
+            sql.push_str(" DO NOTHING");
+        } else {
+            if conflict_columns.is_empty() {
+                return Err(anyhow!(
+                    "Upserting into {} needs a conflict target, mark a field `#[usual(primary_key)]` or call `on_conflict`",
+                    self.table
+                ));
+            }
+
+            let assignments = updated_columns
+                .iter()
+                .map(|column| {
+                    let column = column_sql(column);
+                    format!("{} = EXCLUDED.{}", column, column)
+                })
+                .collect::<Vec<_>>();
+            sql.push_str(&format!(" DO UPDATE SET {}", assignments.join(", ")));
+        }
+
+        if let Some(returning) = returning {
+            sql.push_str(&format!(" RETURNING {}", returning));
+        }
+
+        Ok(Statement { sql, params })
+    }
+}
+
+/// A model that remembers which of its fields were changed, so saving it only writes those:
+///
+/// ```ignore
+/// let mut post = Tracked::new(Post::from_row(&row));
+/// set_fields!(post, title = "New title".to_string());
+///
+/// if let Some(statement) = Update::new("posts", &post).statement()? {
+///     client.execute(statement.sql.as_str(), &statement.params).await?;
+///     post.mark_saved();
+/// }
+/// ```
+///
+/// The primary key's values are copied when the model is tracked, and again when it's saved, so
+/// changing the key itself still updates the row it was loaded from.
+pub struct Tracked<M: ToRow> {
+    model: M,
+    changed: Vec<&'static str>,
+    saved_key: Vec<OwnedParam>,
+}
+
+impl<M: ToRow> Tracked<M> {
+    pub fn new(model: M) -> Self {
+        Tracked {
+            saved_key: model.primary_key_values(),
+            model,
+            changed: vec![],
+        }
+    }
+
+    /// Marks `field` as changed, and gives access to the model to change it. Prefer
+    /// `set_fields!`, which can't name a different field than the one it changes. A field that
+    /// isn't a column, like an `#[unusual]` one, can be changed but isn't marked, since there's
+    /// nothing to write for it.
+    pub fn change(&mut self, field: &'static str) -> &mut M {
+        if M::fields_list().contains(&field) && !self.changed.contains(&field) {
+            self.changed.push(field);
+        }
+
+        &mut self.model
+    }
+
+    /// The fields changed since the model was loaded or last saved, in the order they were
+    /// first changed.
+    pub fn changed_fields(&self) -> &[&'static str] {
+        &self.changed
+    }
+
+    pub fn is_changed(&self) -> bool {
+        !self.changed.is_empty()
+    }
+
+    /// Forgets the changes, once they've been written.
+    pub fn mark_saved(&mut self) {
+        self.changed.clear();
+        self.saved_key = self.model.primary_key_values();
+    }
+
+    pub fn into_inner(self) -> M {
+        self.model
+    }
+}
+
+impl<M: ToRow> Deref for Tracked<M> {
+    type Target = M;
+
+    fn deref(&self) -> &M {
+        &self.model
+    }
+}
+
+/// Sets fields of a [`Tracked`] model, marking each of them as changed, for example
+/// `set_fields!(post, title = "New title".to_string(), views = 0)`.
+#[macro_export]
+macro_rules! set_fields {
+  ($tracked:expr, $($field:ident = $value:expr),+ $(,)?) => {
+      $(
+          $tracked.change(stringify!($field)).$field = $value;
+      )+
+  };
+}
+
+/// Writes the changed fields of a [`Tracked`] model back to its row, found by the primary key it
+/// had when it was loaded or last saved: `UPDATE posts SET title = $1 WHERE id = $2`.
+pub struct Update<'a, M: ToRow> {
+    table: &'a str,
+    tracked: &'a Tracked<M>,
+    dialect: Dialect,
+    returning: bool,
+}
+
+impl<'a, M: ToRow> Update<'a, M> {
+    pub fn new(table: &'a str, tracked: &'a Tracked<M>) -> Self {
+        Update {
+            table,
+            tracked,
+            dialect: Dialect::default(),
+            returning: false,
+        }
+    }
+
+    pub fn dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+
+    /// Returns every column of the updated row, under the model's prefix. MySQL can't return
+    /// rows, so building the statement for it is an error.
+    pub fn returning(mut self) -> Self {
+        self.returning = true;
+        self
+    }
+
+    /// The `UPDATE` statement, or `None` if no field was changed. It's an error if the statement
+    /// can't be written for the dialect, or the model's primary key values aren't known.
+    pub fn statement(&self) -> Result<Option<Statement<'a>>, Error> {
+        if !self.tracked.is_changed() {
+            return Ok(None);
+        }
+
+        let returning = returning_columns::<M>(self.dialect, self.returning, self.table)?;
+        let primary_key = M::primary_key_columns();
+        let quoted = M::quoted_columns();
+        let changed = columns_for_fields::<M>(self.tracked.changed_fields());
+        let row = self.tracked.model.to_row();
+        let value_of = |column: &str| {
+            row.iter()
+                .find(|(known, _)| *known == column)
+                .map(|(_, value)| *value)
+                .unwrap()
+        };
+
+        let mut params = vec![];
+        let mut assignments = vec![];
+        for column in changed {
+            params.push(value_of(column));
+            assignments.push(format!(
+                "{} = {}",
+                column_sql(self.dialect, column, &quoted),
+                self.dialect.placeholder(params.len())
+            ));
+        }
+
+        let condition = primary_key_condition(
+            self.dialect,
+            self.table,
+            &primary_key,
+            &quoted,
+            params.len() + 1,
+        );
+        if self.tracked.saved_key.len() != primary_key.len() {
+            return Err(anyhow!(
+                "Updating {} needs the values of its primary key, implement `ToRow::primary_key_values`",
+                self.table
+            ));
+        }
+        params.extend(
+            self.tracked
+                .saved_key
+                .iter()
+                .map(|value| &**value as SqlParam<'a>),
+        );
+
+        let mut sql = format!(
+            "UPDATE {} SET {} WHERE {}",
+            self.table,
+            assignments.join(", "),
+            condition
+        );
+        if let Some(returning) = returning {
+            sql.push_str(&format!(" RETURNING {}", returning));
+        }
+
+        Ok(Some(Statement { sql, params }))
+    }
+}
+
+/// The columns a statement returns, if `returning` was asked for. MySQL can't return rows.
+fn returning_columns<M: Model>(
+    dialect: Dialect,
+    returning: bool,
+    table: &str,
+) -> Result<Option<String>, Error> {
+    match (returning, dialect) {
+        (false, _) => Ok(None),
+        (true, Dialect::MySql) => Err(anyhow!(
+            "MySQL can't return the rows written to {}, select them afterwards instead",
+            table
+        )),
+        (true, Dialect::Postgres | Dialect::Sqlite) => Ok(Some(M::columns())),
+    }
+}
+
+/// The columns of `fields` on the model `M`.
+fn columns_for_fields<M: Model>(fields: &[&str]) -> Vec<&'static str> {
+    let known_fields = M::fields_list();
+    let columns = M::columns_list();
+
+    fields
+        .iter()
+        .map(|field| {
+            let index = known_fields
+                .iter()
+                .position(|known| known == field)
+                .unwrap_or_else(|| {
+                    panic!("{} is not a field of {}", field, std::any::type_name::<M>())
+                });
+
+            columns[index]
+        })
+        .collect()
+}
+
+/// A column as it's written in a statement, quoted for the dialect if it's one of `quoted`.
+pub(crate) fn column_sql(dialect: Dialect, column: &str, quoted: &[&str]) -> String {
+    if quoted.contains(&column) {
+        dialect.quote(column)
+    } else {
+        column.to_owned()
+    }
+}
+
+/// Matches a row by its primary key, `id = $1`, or `(a, b) = ($1, $2)` for a composite key, with
+/// placeholders numbered from `first_index`.
+pub(crate) fn primary_key_condition(
+    dialect: Dialect,
+    table: &str,
+    columns: &[&str],
+    quoted: &[&str],
+    first_index: usize,
+) -> String {
+    let columns = columns
+        .iter()
+        .map(|column| column_sql(dialect, column, quoted))
+        .collect::<Vec<_>>();
+    let placeholders = (first_index..first_index + columns.len())
+        .map(|index| dialect.placeholder(index))
+        .collect::<Vec<_>>();
+
+    match columns.len() {
+        0 => panic!(
+            "Finding a row of {} needs a primary key, mark a field `#[usual(primary_key)]`.",
+            table
+        ),
+        1 => format!("{} = {}", columns[0], placeholders[0]),
+        _ => format!("({}) = ({})", columns.join(", "), placeholders.join(", ")),
+    }
+}
+
+/// `INSERT INTO table (columns) VALUES (...), (...)` for `rows` rows, numbering the placeholders
+/// row by row.
+pub(crate) fn insert_sql(
+    dialect: Dialect,
+    table: &str,
+    columns: &[&str],
+    quoted: &[&str],
+    rows: usize,
+    returning: Option<&str>,
+) -> String {
+    let mut sql = if columns.is_empty() {
+        format!("INSERT INTO {} DEFAULT VALUES", table)
+    } else {
+        let values = (0..rows)
+            .map(|row| {
+                let placeholders = (1..=columns.len())
+                    .map(|index| dialect.placeholder(row * columns.len() + index))
+                    .collect::<Vec<_>>();
+
+                format!("({})", placeholders.join(", "))
+            })
+            .collect::<Vec<_>>();
+
+        format!(
+            "INSERT INTO {} ({}) VALUES {}",
+            table,
+            columns
+                .iter()
+                .map(|column| column_sql(dialect, column, quoted))
+                .collect::<Vec<_>>()
+                .join(", "),
+            values.join(", ")
+        )
+    };
+
+    if let Some(returning) = returning {
+        sql.push_str(" RETURNING ");
+        sql.push_str(returning);
+    }
+
+    sql
+}
+
+#[cfg(test)]
+mod tests {
+    use usual_proc::{query, UsualModel};
+
+    use super::{BatchInsert, Statement, Tracked, Update, Upsert};
+    use crate::{
+        base::{Error, Model, SqlParam, TryGetRow},
+        dialect::Dialect,
+    };
+
+    #[derive(UsualModel)]
+    struct Post {
+        #[usual(primary_key, generated)]
+        pub id: i64,
+        pub title: String,
+        pub content: String,
+    }
+
+    #[derive(UsualModel)]
+    struct Page {
+        #[usual(primary_key, generated)]
+        pub id: i64,
+        #[usual(rename = "page_slug")]
+        pub slug: String,
+        pub body: String,
+        pub views: i64,
+        #[usual(db_default)]
+        pub created_at: i64,
+        #[unusual]
+        pub cache: Option<String>,
+    }
+
+    #[derive(UsualModel)]
+    struct Order {
+        #[usual(primary_key, generated)]
+        pub id: i64,
+        #[usual(quote)]
+        pub order: i64,
+    }
+
+    fn page() -> Page {
+        Page {
+            id: 1,
+            slug: "home".to_string(),
+            body: "Welcome".to_string(),
+            views: 0,
+            created_at: 0,
+            cache: None,
+        }
+    }
+
+    fn posts(count: i64) -> Vec<Post> {
+        (0..count)
+            .map(|id| Post {
+                id,
+                title: format!("title {}", id),
+                content: "content".to_string(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn it_should_insert_many_rows_in_one_statement() {
+        let posts = posts(3);
+
+        let statements = BatchInsert::new("posts", &posts).statements().unwrap();
+
+        assert!(
+            statements.len() == 1
+                && statements[0].sql
+                    == "INSERT INTO posts (title, content) VALUES ($1, $2), ($3, $4), ($5, $6)",
+            "It should insert every row with a single statement."
+        );
+        assert!(
+            statements[0].params.len() == 6,
+            "It should flatten the parameters of every row."
+        );
+    }
+
+    #[test]
+    fn it_should_chunk_under_the_parameter_limit() {
+        let posts = posts(5);
+
+        let statements = BatchInsert::new("posts", &posts)
+            .dialect(Dialect::Sqlite)
+            .max_params(5)
+            .returning()
+            .statements()
+            .unwrap();
+
+        assert!(
+            statements.len() == 3,
+            "It should split the rows into statements under the parameter limit."
+        );
+        assert!(
+            statements[0].sql
+                == "INSERT INTO posts (title, content) VALUES (?1, ?2), (?3, ?4) RETURNING id as Post__id, title as Post__title, content as Post__content",
+            "It should number placeholders for the dialect and return the inserted rows."
+        );
+        assert!(
+            statements[2]
+                .sql
+                .starts_with("INSERT INTO posts (title, content) VALUES (?1, ?2) RETURNING")
+                && statements[2].params.len() == 2,
+            "It should put the remaining rows in the last statement."
+        );
+    }
+
+    #[test]
+    fn it_should_upsert_on_the_primary_key() {
+        let page = page();
+
+        let statement = Upsert::new("pages", &page).returning().statement().unwrap();
+
+        assert!(
+            statement.sql
+                == "INSERT INTO pages (id, page_slug, body, views) VALUES ($1, $2, $3, $4) ON CONFLICT (id) DO UPDATE SET page_slug = EXCLUDED.page_slug, body = EXCLUDED.body, views = EXCLUDED.views RETURNING id as Page__id, page_slug as Page__page_slug, body as Page__body, views as Page__views, created_at as Page__created_at",
+            "It should update every other column when the primary key conflicts."
+        );
+        assert!(
+            statement.params.len() == 4,
+            "It should pass the primary key along with the other values."
+        );
+    }
+
+    #[test]
+    fn it_should_upsert_on_named_fields() {
+        let page = page();
+
+        let statement = Upsert::new("pages", &page)
+            .dialect(Dialect::Sqlite)
+            .on_conflict(&["slug"])
+            .exclude(&["views"])
+            .statement()
+            .unwrap();
+
+        assert!(
+            statement.sql
+                == "INSERT INTO pages (page_slug, body, views) VALUES (?1, ?2, ?3) ON CONFLICT (page_slug) DO UPDATE SET body = EXCLUDED.body",
+            "It should conflict on the named fields and leave the generated primary key and excluded fields alone."
+        );
+
+        let statement = Upsert::new("pages", &page)
+            .on_conflict(&["slug"])
+            .do_nothing()
+            .statement()
+            .unwrap();
+
+        assert!(
+            statement.sql
+                == "INSERT INTO pages (page_slug, body, views) VALUES ($1, $2, $3) ON CONFLICT (page_slug) DO NOTHING"
+                && statement.params.len() == 3,
+            "It should leave a generated primary key to the database when it isn't the conflict target."
+        );
+
+        let statement = Upsert::new("pages", &page)
+            .do_nothing()
+            .statement()
+            .unwrap();
+
+        assert!(
+            statement.sql
+                == "INSERT INTO pages (id, page_slug, body, views) VALUES ($1, $2, $3, $4) ON CONFLICT (id) DO NOTHING",
+            "It should be able to leave existing rows alone."
+        );
+
+        let statement = Upsert::new("pages", &page)
+            .on_conflict(&[])
+            .do_nothing()
+            .statement()
+            .unwrap();
+
+        assert!(
+            statement.sql
+                == "INSERT INTO pages (page_slug, body, views) VALUES ($1, $2, $3) ON CONFLICT DO NOTHING",
+            "It should leave rows conflicting on any constraint alone without a conflict target."
+        );
+    }
+
+    #[test]
+    fn it_should_upsert_on_mysql() {
+        let page = page();
+
+        let statement = Upsert::new("pages", &page)
+            .dialect(Dialect::MySql)
+            .exclude(&["views"])
+            .statement()
+            .unwrap();
+
+        assert!(
+            statement.sql
+                == "INSERT INTO pages (id, page_slug, body, views) VALUES (?, ?, ?, ?) ON DUPLICATE KEY UPDATE page_slug = VALUES(page_slug), body = VALUES(body)",
+            "It should update on duplicate keys."
+        );
+
+        let statement = Upsert::new("pages", &page)
+            .dialect(Dialect::MySql)
+            .do_nothing()
+            .statement()
+            .unwrap();
+
+        assert!(
+            statement.sql
+                == "INSERT IGNORE INTO pages (id, page_slug, body, views) VALUES (?, ?, ?, ?)",
+            "It should ignore duplicates."
+        );
+    }
+
+    #[test]
+    fn it_should_refuse_to_return_rows_on_mysql() {
+        let posts = posts(1);
+        let mut page = Tracked::new(page());
+        set_fields!(page, views = 1);
+
+        let refused = |result: Result<(), Error>, table: &str| matches!(result, Err(e) if e.to_string() == format!("MySQL can't return the rows written to {}, select them afterwards instead", table));
+
+        assert!(
+            refused(
+                BatchInsert::new("posts", &posts)
+                    .dialect(Dialect::MySql)
+                    .returning()
+                    .statements()
+                    .map(drop),
+                "posts"
+            ),
+            "It should fail instead of returning inserted rows on MySQL."
+        );
+        assert!(
+            refused(
+                Upsert::new("pages", &*page)
+                    .dialect(Dialect::MySql)
+                    .returning()
+                    .statement()
+                    .map(drop),
+                "pages"
+            ),
+            "It should fail instead of returning upserted rows on MySQL."
+        );
+        assert!(
+            refused(
+                Update::new("pages", &page)
+                    .dialect(Dialect::MySql)
+                    .returning()
+                    .statement()
+                    .map(drop),
+                "pages"
+            ),
+            "It should fail instead of returning updated rows on MySQL."
+        );
+    }
+
+    #[test]
+    fn it_should_rewrite_statements_for_a_dialect() {
+        let author = 1i64;
+        let since = 2i64;
+
+        let statement = query!(
+            "SELECT {Post::id} FROM posts WHERE (author = :author OR editor = :author) AND created_at > :since",
+            author = &author,
+            since = &since,
+        )
+        .dialect(Dialect::MySql)
+        .unwrap();
+
+        assert!(
+            statement.sql
+                == "SELECT id as Post__id FROM posts WHERE (author = ? OR editor = ?) AND created_at > ?",
+            "It should replace the placeholders."
+        );
+        assert!(
+            statement.params.len() == 3,
+            "It should bind a parameter for each placeholder."
+        );
+
+        let statement = Statement {
+            sql: "SELECT id FROM posts WHERE author = $1 AND editor = $2".to_string(),
+            params: vec![&author],
+        };
+
+        assert!(
+            matches!(statement.dialect(Dialect::MySql), Err(e) if e.to_string() == "The statement uses $2, which has no parameter to bind"),
+            "It should fail instead of panicking when a placeholder has no parameter."
+        );
+    }
+
+    #[test]
+    fn it_should_update_only_changed_fields() {
+        let mut page = Tracked::new(page());
+
+        assert!(
+            Update::new("pages", &page).statement().unwrap().is_none(),
+            "It should not update a model that wasn't changed."
+        );
+
+        set_fields!(page, views = 10, slug = "start".to_string());
+        set_fields!(page, views = 11);
+
+        let statement = Update::new("pages", &page).statement().unwrap().unwrap();
+
+        assert!(
+            statement.sql == "UPDATE pages SET views = $1, page_slug = $2 WHERE id = $3",
+            "It should only set the changed columns, and find the row by its primary key."
+        );
+        assert!(
+            statement.params.len() == 3 && page.views == 11,
+            "It should pass the changed values and the primary key."
+        );
+
+        page.mark_saved();
+
+        assert!(
+            !page.is_changed(),
+            "It should forget the changes once they're saved."
+        );
+
+        set_fields!(page, cache = Some("cached".to_string()));
+
+        assert!(
+            page.cache.as_deref() == Some("cached")
+                && Update::new("pages", &page).statement().unwrap().is_none(),
+            "It should change unusual fields without writing them."
+        );
+    }
+
+    #[cfg(not(feature = "tokio-postgres"))]
+    fn param_as_i64(param: SqlParam<'_>) -> i64 {
+        *param.to_any().downcast::<i64>().unwrap()
+    }
+
+    #[cfg(feature = "tokio-postgres")]
+    fn param_as_i64(param: SqlParam<'_>) -> i64 {
+        use tokio_postgres::types::{FromSql, Type};
+
+        let mut buf = Default::default();
+        param.to_sql_checked(&Type::INT8, &mut buf).unwrap();
+        i64::from_sql(&Type::INT8, &buf).unwrap()
+    }
+
+    #[test]
+    fn it_should_find_the_row_by_the_key_it_was_loaded_with() {
+        let mut page = Tracked::new(page());
+        set_fields!(page, id = 2, views = 1);
+
+        let statement = Update::new("pages", &page).statement().unwrap().unwrap();
+
+        assert!(
+            statement.sql == "UPDATE pages SET id = $1, views = $2 WHERE id = $3",
+            "It should set the new primary key like any other changed field."
+        );
+        assert!(
+            param_as_i64(statement.params[0]) == 2 && param_as_i64(statement.params[2]) == 1,
+            "It should find the row by the primary key it was loaded with."
+        );
+
+        page.mark_saved();
+        set_fields!(page, views = 2);
+
+        let statement = Update::new("pages", &page).statement().unwrap().unwrap();
+
+        assert!(
+            param_as_i64(statement.params[1]) == 2,
+            "It should find the row by the new primary key once it's saved."
+        );
+    }
+
+    #[test]
+    fn it_should_quote_columns_for_the_dialect() {
+        let mut order = Tracked::new(Order { id: 1, order: 2 });
+        set_fields!(order, order = 3);
+
+        assert!(
+            Update::new("orders", &order)
+                .statement()
+                .unwrap()
+                .unwrap()
+                .sql
+                == r#"UPDATE orders SET "order" = $1 WHERE id = $2"#,
+            "It should quote columns with double quotes for Postgres."
+        );
+        assert!(
+            Update::new("orders", &order)
+                .dialect(Dialect::MySql)
+                .statement()
+                .unwrap()
+                .unwrap()
+                .sql
+                == "UPDATE orders SET `order` = ? WHERE id = ?",
+            "It should quote columns with backticks for MySQL."
+        );
+    }
+}
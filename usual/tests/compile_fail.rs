@@ -0,0 +1,5 @@
+#[test]
+fn it_should_reject_invalid_macro_input() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
@@ -0,0 +1,13 @@
+use usual::{
+    base::{Model, TryGetRow},
+    UsualModel,
+};
+
+// Both aliases are cut to the same 54 bytes, and their hashes collide.
+#[derive(UsualModel)]
+struct ModelWithAnUnusuallyLongNameWhoseAliasesAllGetShortened {
+    field_824938: i64,
+    field_1178906: i64,
+}
+
+fn main() {}
@@ -0,0 +1,17 @@
+use usual::{
+    base::{Model, TryGetRow},
+    partial,
+    testing::MockRow,
+    UsualModel,
+};
+
+#[derive(UsualModel)]
+struct Post {
+    pub id: i64,
+    pub title: String,
+}
+
+fn main() {
+    let row = MockRow::new();
+    let _ = (partial!(Post, titel as String))(&row);
+}
@@ -0,0 +1,14 @@
+use usual::{
+    base::{Model, TryGetRow},
+    query, UsualModel,
+};
+
+#[derive(UsualModel)]
+struct Post {
+    pub id: i64,
+    pub title: String,
+}
+
+fn main() {
+    let _ = query!("SELECT {Post::id, title} FROM posts");
+}
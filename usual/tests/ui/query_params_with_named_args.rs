@@ -0,0 +1,19 @@
+use usual::{
+    base::{Model, TryGetRow},
+    query, UsualModel,
+};
+
+#[derive(UsualModel)]
+struct Post {
+    pub id: i64,
+    pub title: String,
+}
+
+fn main() {
+    let author = 1_i64;
+
+    let _ = query!(
+        "INSERT INTO posts (author, {Post:cols}) VALUES (:author, {Post:params})",
+        author = &author,
+    );
+}
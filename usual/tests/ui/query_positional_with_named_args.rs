@@ -0,0 +1,10 @@
+use usual::query;
+
+fn main() {
+    let author = 1_i64;
+
+    let _ = query!(
+        "SELECT id FROM posts WHERE editor = $1 AND author = :author",
+        author = &author,
+    );
+}
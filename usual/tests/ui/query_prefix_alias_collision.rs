@@ -0,0 +1,15 @@
+use usual::{
+    base::{Model, TryGetRow},
+    query, UsualModel,
+};
+
+#[derive(UsualModel)]
+struct Post {
+    field_89267: i64,
+    field_248000: i64,
+}
+
+fn main() {
+    // Under this prefix, both aliases are cut to the same 54 bytes, and their hashes collide.
+    let _ = query!("SELECT {Post prefix a_prefix_long_enough_for_every_alias_to_be_shortened_by_hash} FROM posts");
+}
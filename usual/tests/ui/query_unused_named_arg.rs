@@ -0,0 +1,12 @@
+use usual::query;
+
+fn main() {
+    let author = 1_i64;
+    let since = 2_i64;
+
+    let _ = query!(
+        "SELECT id FROM posts WHERE author = :author",
+        author = &author,
+        since = &since,
+    );
+}